@@ -0,0 +1,79 @@
+//! The lines starting with `;` and `:`, which MediaWiki makes into
+//! definition lists, whether they are terms and definitions or just text
+//! that is indented.
+
+use parse_wiki_text::Node;
+
+/// The nodes of a `; term : definition` line, split at the first colon
+/// that is not inside something else, like a link. The colon is in the
+/// middle of a text node, so that node's text is split in two.
+pub struct SplitTerm<'nodes, 'text> {
+    pub term: &'nodes [Node<'text>],
+    pub term_end: &'nodes str,
+    pub definition_start: &'nodes str,
+    pub definition: &'nodes [Node<'text>],
+}
+
+/// Returns the term split from its definition, or `None` if the term has
+/// no definition on the same line.
+pub fn split_term<'nodes, 'text>(
+    nodes: &'nodes [Node<'text>]
+) -> Option<SplitTerm<'nodes, 'text>> {
+    nodes.iter().enumerate().find_map(|(i, node)| match node {
+        Node::Text { value, .. } => value.find(':').map(|colon| SplitTerm {
+            term: &nodes[..i],
+            term_end: &value[..colon],
+            definition_start: &value[colon + 1..],
+            definition: &nodes[i + 1..],
+        }),
+        _ => None,
+    })
+}
+
+/// Returns the text with tables that are indented, like `:{|`, wrapped in
+/// as many definition lists as there are colons, which is how MediaWiki
+/// indents them. The parser doesn't know about these, and would only see
+/// the first line of the table as indented text.
+pub fn indent_tables(text: &str) -> String {
+    let mut indented = String::with_capacity(text.len());
+
+    // How many levels each of the tables that are still open is indented by.
+    let mut open_tables: Vec<usize> = Vec::new();
+
+    for line in text.split_inclusive('\n') {
+        let after_colons = line.trim_start_matches(':');
+        let levels = line.len() - after_colons.len();
+
+        if levels > 0 && after_colons.trim_start().starts_with("{|") {
+            indented.push_str(&"<dl><dd>".repeat(levels));
+            indented.push('\n');
+            indented.push_str(after_colons.trim_start());
+            open_tables.push(levels);
+            continue
+        }
+
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("{|") {
+            open_tables.push(0);
+        }
+
+        indented.push_str(line);
+
+        if trimmed.starts_with("|}") {
+            match open_tables.pop() {
+                Some(levels) if levels > 0 => {
+                    if !line.ends_with('\n') {
+                        indented.push('\n');
+                    }
+                    indented.push_str(&"</dd></dl>".repeat(levels));
+                    if line.ends_with('\n') {
+                        indented.push('\n');
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    indented
+}
@@ -0,0 +1,505 @@
+//! An evaluator for the expressions used by `{{#expr:...}}` and
+//! `{{#ifexpr:...}}`, following the operators and precedences of the
+//! ParserFunctions extension. Everything is evaluated as a float, just like
+//! the extension does.
+
+/// The result of evaluating an expression, where the error is the message
+/// to show to the reader.
+pub type ExprResult = Result<f64, String>;
+
+pub fn evaluate(expression: &str) -> ExprResult {
+    let tokens = tokenize(expression)?;
+
+    let mut parser = Parser { tokens, position: 0 };
+
+    let value = parser.binary(0)?;
+
+    match parser.tokens.get(parser.position) {
+        None => Ok(value),
+        Some(Token::Number(_)) | Some(Token::Constant(_)) => {
+            Err("Unexpected number.".to_owned())
+        },
+        Some(Token::Close) => Err("Unexpected closing bracket.".to_owned()),
+        Some(token) => Err(format!("Unexpected {} operator.", token.name())),
+    }
+}
+
+/// Formats a number the way PHP does by default, which is how the
+/// ParserFunctions extension outputs results.
+pub fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        return "NAN".to_owned();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "INF" } else { "-INF" }.to_owned();
+    }
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+
+    // PHP uses 14 significant digits.
+    let scientific = format!("{:.13e}", value);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap_or(scientific.len()));
+    let exponent: i32 = exponent.trim_start_matches('e').parse().unwrap_or(0);
+
+    fn trim_zeros(s: &str) -> &str {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            s
+        }
+    }
+
+    if !(-4..15).contains(&exponent) {
+        let mut mantissa = trim_zeros(mantissa).to_owned();
+        if !mantissa.contains('.') {
+            mantissa.push_str(".0");
+        }
+        format!("{}E{}{}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+    } else {
+        let decimals = (13 - exponent).max(0) as usize;
+        trim_zeros(&format!("{:.*}", decimals, value)).to_owned()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Constant(f64),
+    Open,
+    Close,
+    Operator(Operator),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    // Binary operators.
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Round,
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Mod,
+    FMod,
+    Pow,
+    Exponent,
+    // Unary operators.
+    Not,
+    Sin,
+    Cos,
+    Tan,
+    ASin,
+    ACos,
+    ATan,
+    Ln,
+    Exp,
+    Abs,
+    Floor,
+    Ceil,
+    Trunc,
+    Sqrt,
+}
+
+impl Token {
+    fn name(&self) -> &'static str {
+        use Operator::*;
+        match self {
+            Token::Number(_) | Token::Constant(_) => "number",
+            Token::Open => "(",
+            Token::Close => ")",
+            Token::Operator(operator) => match operator {
+                Or => "or",
+                And => "and",
+                Equal => "=",
+                NotEqual => "!=",
+                Less => "<",
+                Greater => ">",
+                LessOrEqual => "<=",
+                GreaterOrEqual => ">=",
+                Round => "round",
+                Plus => "+",
+                Minus => "-",
+                Times => "*",
+                Divide => "/",
+                Mod => "mod",
+                FMod => "fmod",
+                Pow => "^",
+                Exponent => "e",
+                Not => "not",
+                Sin => "sin",
+                Cos => "cos",
+                Tan => "tan",
+                ASin => "asin",
+                ACos => "acos",
+                ATan => "atan",
+                Ln => "ln",
+                Exp => "exp",
+                Abs => "abs",
+                Floor => "floor",
+                Ceil => "ceil",
+                Trunc => "trunc",
+                Sqrt => "sqrt",
+            },
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    use Operator::*;
+
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expression.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let number = number.parse::<f64>()
+                .map_err(|_| format!("Invalid number \"{}\".", number))?;
+            tokens.push(Token::Number(number));
+            continue
+        }
+
+        if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+            tokens.push(match word.as_str() {
+                "or" => Token::Operator(Or),
+                "and" => Token::Operator(And),
+                "round" => Token::Operator(Round),
+                "div" => Token::Operator(Divide),
+                "mod" => Token::Operator(Mod),
+                "fmod" => Token::Operator(FMod),
+                "not" => Token::Operator(Not),
+                "sin" => Token::Operator(Sin),
+                "cos" => Token::Operator(Cos),
+                "tan" => Token::Operator(Tan),
+                "asin" => Token::Operator(ASin),
+                "acos" => Token::Operator(ACos),
+                "atan" => Token::Operator(ATan),
+                "ln" => Token::Operator(Ln),
+                "exp" => Token::Operator(Exp),
+                "abs" => Token::Operator(Abs),
+                "floor" => Token::Operator(Floor),
+                "ceil" => Token::Operator(Ceil),
+                "trunc" => Token::Operator(Trunc),
+                "sqrt" => Token::Operator(Sqrt),
+                "pi" => Token::Constant(std::f64::consts::PI),
+                // `e` is Euler's number where a number is expected, and the
+                // exponent operator, as in `2e3`, elsewhere.
+                "e" => match tokens.last() {
+                    Some(Token::Number(_))
+                    | Some(Token::Constant(_))
+                    | Some(Token::Close) => Token::Operator(Exponent),
+                    _ => Token::Constant(std::f64::consts::E),
+                },
+                _ => return Err(format!("Unrecognized word \"{}\".", word)),
+            });
+            continue
+        }
+
+        let next = chars.get(i + 1).copied();
+        let (token, length) = match (c, next) {
+            ('!', Some('=')) => (Token::Operator(NotEqual), 2),
+            ('<', Some('>')) => (Token::Operator(NotEqual), 2),
+            ('<', Some('=')) => (Token::Operator(LessOrEqual), 2),
+            ('>', Some('=')) => (Token::Operator(GreaterOrEqual), 2),
+            ('<', _) => (Token::Operator(Less), 1),
+            ('>', _) => (Token::Operator(Greater), 1),
+            ('=', _) => (Token::Operator(Equal), 1),
+            ('+', _) => (Token::Operator(Plus), 1),
+            ('-', _) => (Token::Operator(Minus), 1),
+            ('*', _) => (Token::Operator(Times), 1),
+            ('/', _) => (Token::Operator(Divide), 1),
+            ('^', _) => (Token::Operator(Pow), 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            _ => return Err(format!("Unrecognized punctuation character \"{}\".", c)),
+        };
+        tokens.push(token);
+        i += length;
+    }
+
+    Ok(tokens)
+}
+
+/// The precedence of the operator when used between two operands.
+fn binary_precedence(operator: Operator) -> Option<u8> {
+    use Operator::*;
+    match operator {
+        Or => Some(2),
+        And => Some(3),
+        Equal | NotEqual | Less | Greater | LessOrEqual | GreaterOrEqual => Some(4),
+        Round => Some(5),
+        Plus | Minus => Some(6),
+        Times | Divide | Mod | FMod => Some(7),
+        Pow => Some(8),
+        Exponent => Some(10),
+        _ => None,
+    }
+}
+
+/// The precedence of the operator when used before a single operand.
+fn unary_precedence(operator: Operator) -> Option<u8> {
+    use Operator::*;
+    match operator {
+        Plus | Minus => Some(10),
+        Not | Sin | Cos | Tan | ASin | ACos | ATan | Ln | Exp | Abs | Floor
+        | Ceil | Trunc | Sqrt => Some(9),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    /// Parses operators with at least the given precedence, climbing up to
+    /// the higher precedences as needed.
+    fn binary(&mut self, min_precedence: u8) -> ExprResult {
+        let mut left = self.unary()?;
+
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position).copied() {
+            let precedence = match binary_precedence(operator) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => break,
+            };
+            self.position += 1;
+
+            if self.tokens.get(self.position).is_none() {
+                return Err(format!("Missing operand for {}.", Token::Operator(operator).name()));
+            }
+
+            // All the operators are left associative.
+            let right = self.binary(precedence + 1)?;
+
+            left = apply_binary(operator, left, right)?;
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> ExprResult {
+        match self.tokens.get(self.position).copied() {
+            Some(Token::Number(value)) | Some(Token::Constant(value)) => {
+                self.position += 1;
+                match self.tokens.get(self.position) {
+                    Some(Token::Number(_)) | Some(Token::Constant(_)) => {
+                        Err("Unexpected number.".to_owned())
+                    },
+                    Some(Token::Open) => Err("Unexpected ( operator.".to_owned()),
+                    _ => Ok(value),
+                }
+            },
+            Some(Token::Open) => {
+                self.position += 1;
+                let value = self.binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(value)
+                    },
+                    _ => Err("Unclosed bracket.".to_owned()),
+                }
+            },
+            Some(Token::Operator(operator)) => {
+                let precedence = unary_precedence(operator).ok_or_else(|| {
+                    format!("Missing operand for {}.", Token::Operator(operator).name())
+                })?;
+                self.position += 1;
+
+                if self.tokens.get(self.position).is_none() {
+                    return Err(format!("Missing operand for {}.", Token::Operator(operator).name()));
+                }
+
+                let operand = self.binary(precedence)?;
+
+                apply_unary(operator, operand)
+            },
+            Some(Token::Close) => Err("Unexpected closing bracket.".to_owned()),
+            None => Err("Unexpected end of expression.".to_owned()),
+        }
+    }
+}
+
+fn apply_binary(operator: Operator, left: f64, right: f64) -> ExprResult {
+    use Operator::*;
+
+    let truth = |b: bool| if b { 1.0 } else { 0.0 };
+
+    Ok(match operator {
+        Or => truth(left != 0.0 || right != 0.0),
+        And => truth(left != 0.0 && right != 0.0),
+        Equal => truth(left == right),
+        NotEqual => truth(left != right),
+        Less => truth(left < right),
+        Greater => truth(left > right),
+        LessOrEqual => truth(left <= right),
+        GreaterOrEqual => truth(left >= right),
+        Round => {
+            let digits = right.trunc() as i32;
+            let scale = 10f64.powi(digits);
+            (left * scale).round() / scale
+        },
+        Plus => left + right,
+        Minus => left - right,
+        Times => left * right,
+        Divide => {
+            if right == 0.0 {
+                return Err("Division by zero.".to_owned());
+            }
+            left / right
+        },
+        Mod => {
+            // Like PHP's `%`, this works on integers.
+            let (left, right) = (left.trunc() as i64, right.trunc() as i64);
+            if right == 0 {
+                return Err("Division by zero.".to_owned());
+            }
+            left.wrapping_rem(right) as f64
+        },
+        FMod => {
+            if right == 0.0 {
+                return Err("Division by zero.".to_owned());
+            }
+            left % right
+        },
+        Pow => {
+            let value = left.powf(right);
+            if value.is_nan() {
+                return Err("Invalid argument for ^.".to_owned());
+            }
+            value
+        },
+        Exponent => left * 10f64.powf(right),
+        _ => unreachable!("{:?} is not a binary operator", operator),
+    })
+}
+
+fn apply_unary(operator: Operator, operand: f64) -> ExprResult {
+    use Operator::*;
+
+    let check = |value: f64| if value.is_nan() {
+        Err(format!("Invalid argument for {}.", Token::Operator(operator).name()))
+    } else {
+        Ok(value)
+    };
+
+    match operator {
+        Plus => Ok(operand),
+        Minus => Ok(-operand),
+        Not => Ok(if operand == 0.0 { 1.0 } else { 0.0 }),
+        Sin => check(operand.sin()),
+        Cos => check(operand.cos()),
+        Tan => check(operand.tan()),
+        ASin => check(operand.asin()),
+        ACos => check(operand.acos()),
+        ATan => check(operand.atan()),
+        Ln => {
+            if operand <= 0.0 {
+                return Err("Invalid argument for ln: <= 0.".to_owned());
+            }
+            Ok(operand.ln())
+        },
+        Exp => check(operand.exp()),
+        Abs => Ok(operand.abs()),
+        Floor => Ok(operand.floor()),
+        Ceil => Ok(operand.ceil()),
+        Trunc => Ok(operand.trunc()),
+        Sqrt => check(operand.sqrt()),
+        _ => unreachable!("{:?} is not a unary operator", operator),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_precedence() {
+        assert_eq!(evaluate("2+3*4"), Ok(14.0));
+        assert_eq!(evaluate("(2+3)*4"), Ok(20.0));
+        assert_eq!(evaluate("2^3^2"), Ok(64.0));
+        assert_eq!(evaluate("1 + 2 = 3 and 4 > 5"), Ok(0.0));
+    }
+
+    #[test]
+    fn binds_unary_minus_tighter_than_powers() {
+        assert_eq!(evaluate("-2^2"), Ok(4.0));
+        assert_eq!(evaluate("2^-1"), Ok(0.5));
+    }
+
+    #[test]
+    fn reads_e_as_exponent_after_a_number() {
+        assert_eq!(evaluate("2e3"), Ok(2000.0));
+        assert_eq!(evaluate("(1+1)e2"), Ok(200.0));
+        assert_eq!(evaluate("e"), Ok(std::f64::consts::E));
+        assert_eq!(evaluate("2*e"), Ok(2.0 * std::f64::consts::E));
+    }
+
+    #[test]
+    fn reports_division_by_zero() {
+        assert_eq!(evaluate("1/0"), Err("Division by zero.".to_owned()));
+        assert_eq!(evaluate("1 mod 0"), Err("Division by zero.".to_owned()));
+        assert_eq!(evaluate("1 fmod 0"), Err("Division by zero.".to_owned()));
+    }
+
+    #[test]
+    fn takes_mod_of_integers() {
+        assert_eq!(evaluate("10 mod 3"), Ok(1.0));
+        assert_eq!(evaluate("-10 mod 3"), Ok(-1.0));
+        assert_eq!(evaluate("10.9 mod 3.9"), Ok(1.0));
+        assert_eq!(evaluate("5.5 fmod 2"), Ok(1.5));
+    }
+
+    #[test]
+    fn rounds_to_digits() {
+        assert_eq!(evaluate("2.5 round 0"), Ok(3.0));
+        assert_eq!(evaluate("1.2345 round 2"), Ok(1.23));
+        assert_eq!(evaluate("1234 round -2"), Ok(1200.0));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert_eq!(evaluate("1 2"), Err("Unexpected number.".to_owned()));
+        assert_eq!(evaluate("(1"), Err("Unclosed bracket.".to_owned()));
+        assert_eq!(evaluate("1)"), Err("Unexpected closing bracket.".to_owned()));
+        assert_eq!(evaluate("1 +"), Err("Missing operand for +.".to_owned()));
+        assert_eq!(evaluate("foo"), Err("Unrecognized word \"foo\".".to_owned()));
+    }
+
+    #[test]
+    fn formats_numbers_like_php() {
+        assert_eq!(format_number(14.0), "14");
+        assert_eq!(format_number(-0.5), "-0.5");
+        assert_eq!(format_number(2f64.sqrt()), "1.4142135623731");
+        assert_eq!(format_number(1e20), "1.0E+20");
+        assert_eq!(format_number(1.5e-7), "1.5E-7");
+        assert_eq!(format_number(f64::INFINITY), "INF");
+        assert_eq!(format_number(f64::NAN), "NAN");
+    }
+}
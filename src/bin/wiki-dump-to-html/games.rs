@@ -0,0 +1,334 @@
+use parse_wiki_text::{Node, Parameter};
+use std::io::Write;
+
+use super::{
+    plain_text,
+    template_name,
+    write_header,
+    Escaped,
+    Page,
+    Res,
+    Site,
+    INDEX_FILE_NAME,
+};
+
+/// The name of the infobox template at the top of every game page.
+pub const GAME: &str = "Game";
+
+/// The title of the generated page that lists all the games.
+pub const GAMES_TITLE: &str = "Games";
+
+/// The catalog of games, for use by other programs.
+pub const GAMES_JSON_FILE_NAME: &str = "games.json";
+
+/// What a `{{Game}}` infobox says about a game. The template calls the
+/// author, genre and player count `first`, `second` and `third`, after the
+/// rows of the box.
+#[derive(Default)]
+pub struct Game {
+    pub title: String,
+    pub author: String,
+    pub genre: String,
+    pub players: String,
+    pub status: String,
+    pub quote: String,
+    pub link: String,
+    pub image: String,
+    pub image_caption: String,
+}
+
+impl Game {
+    pub fn new(page_text: &str, parameters: &[Parameter]) -> Self {
+        let mut game = Game::default();
+
+        for parameter in parameters {
+            let name = match &parameter.name {
+                Some(name) => plain_text(page_text, name),
+                None => continue,
+            };
+
+            let field = match name.as_str() {
+                "title" => &mut game.title,
+                "first" => &mut game.author,
+                "second" => &mut game.genre,
+                "third" => &mut game.players,
+                "fourth" => &mut game.status,
+                "fifth" => &mut game.quote,
+                "sixth" => &mut game.link,
+                "image" => &mut game.image,
+                "imagecaption" => &mut game.image_caption,
+                _ => continue,
+            };
+
+            *field = plain_text(page_text, &parameter.value);
+        }
+
+        game
+    }
+
+    /// Returns the game from the infobox at the top level of the page, if
+    /// there is one, with the title of the page as the title of the game if
+    /// the infobox doesn't give one.
+    pub fn find(page: &Page, nodes: &[Node]) -> Option<Self> {
+        nodes.iter().find_map(|node| match node {
+            Node::Template { name, parameters, .. }
+            if template_name(&page.text, name) == GAME => {
+                let mut game = Game::new(&page.text, parameters);
+                if game.title.is_empty() {
+                    game.title = page.title.clone();
+                }
+                Some(game)
+            },
+            _ => None,
+        })
+    }
+
+    /// Returns the number of players, which the pages mostly give in words.
+    pub fn player_count(&self) -> Option<u32> {
+        let players = self.players.trim();
+
+        ["one", "two", "three", "four"].iter()
+            .position(|word| players.eq_ignore_ascii_case(word))
+            .map(|i| i as u32 + 1)
+            .or_else(|| players.parse().ok())
+    }
+
+    fn players_label(&self) -> String {
+        match self.player_count() {
+            Some(count) => count.to_string(),
+            None => self.players.clone(),
+        }
+    }
+
+    /// Writes the infobox, with the rows that the template would show.
+    pub fn write_infobox(&self, writer: &mut impl Write) -> Res<()> {
+        let unknown = |value: &str| if value.is_empty() {
+            "<i>Unknown</i>".to_owned()
+        } else {
+            Escaped(value).to_string()
+        };
+
+        write!(writer, "<aside class=\"infobox\"><table>")?;
+        write!(
+            writer,
+            "<tr><th colspan=\"2\" class=\"infobox-header\">{}</th></tr>",
+            unknown(&self.title)
+        )?;
+
+        // The image isn't in the dump, but the caption still says something.
+        if !self.image_caption.is_empty() {
+            write!(
+                writer,
+                "<tr><td colspan=\"2\" class=\"infobox-caption\">{}</td></tr>",
+                Escaped(&self.image_caption)
+            )?;
+        }
+
+        for (label, value) in [
+            ("Creator", &self.author),
+            ("Genre", &self.genre),
+            ("Player Count", &self.players),
+        ] {
+            write!(writer, "<tr><th>{}</th><td>{}</td></tr>", label, unknown(value))?;
+        }
+
+        for (label, value) in [
+            ("Status", &self.status),
+            ("Quote", &self.quote),
+            ("Link", &self.link),
+        ] {
+            if !value.is_empty() {
+                write!(writer, "<tr><th>{}</th><td>{}</td></tr>", label, Escaped(value))?;
+            }
+        }
+
+        write!(writer, "</table></aside>")?;
+
+        Ok(())
+    }
+}
+
+/// Writes the catalog of the games, as a table that can be sorted by
+/// clicking on the headings, and filtered by author, genre and player count.
+/// Without scripts, it's still a table sorted by title.
+pub fn write_catalog(
+    writer: &mut impl Write,
+    site: &Site,
+    games: &[(&Page, Game)],
+) -> Res<()> {
+    write_header(writer, GAMES_TITLE, "")?;
+
+    write!(writer, "<nav><a href=\"{}\">Index</a></nav>", INDEX_FILE_NAME)?;
+    write!(writer, "<h1>{}</h1>", Escaped(GAMES_TITLE))?;
+
+    write!(
+        writer,
+        "<p>{} games. The catalog is also available as <a href=\"{}\">JSON</a>.</p>",
+        games.len(),
+        GAMES_JSON_FILE_NAME
+    )?;
+
+    write!(writer, "<form class=\"filters\" hidden>")?;
+    for (column, label) in [(1, "Author"), (2, "Genre"), (3, "Players")] {
+        write!(
+            writer,
+            "<label>{} <select data-column=\"{}\"><option value=\"\">Any</option></select></label> ",
+            label,
+            column
+        )?;
+    }
+    write!(writer, "</form>")?;
+
+    write!(
+        writer,
+        "<table class=\"catalog\"><thead><tr>\
+        <th>Title</th><th>Author</th><th>Genre</th><th>Players</th>\
+        </tr></thead><tbody>"
+    )?;
+    for (page, game) in games {
+        write!(writer, "<tr><td>")?;
+        match site.file_names.get(&page.title) {
+            Some(file_name) => {
+                write!(
+                    writer,
+                    "<a href=\"{}\">{}</a>",
+                    Escaped(file_name),
+                    Escaped(&game.title)
+                )?;
+            },
+            None => write!(writer, "{}", Escaped(&game.title))?,
+        }
+        write!(
+            writer,
+            "</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            Escaped(&game.author),
+            Escaped(&game.genre),
+            Escaped(&game.players_label())
+        )?;
+    }
+    write!(writer, "</tbody></table>")?;
+
+    write!(writer, "<script>{}</script>", CATALOG_SCRIPT)?;
+
+    write!(writer, "</body></html>")?;
+
+    Ok(())
+}
+
+/// Fills in the filters from the values in the table, and makes the
+/// headings sort the table when clicked.
+const CATALOG_SCRIPT: &str = r#"
+(function () {
+    var table = document.querySelector("table.catalog");
+    var body = table.tBodies[0];
+    var rows = Array.prototype.slice.call(body.rows);
+    var selects = document.querySelectorAll(".filters select");
+
+    function text(row, column) {
+        return row.cells[column].textContent.trim();
+    }
+
+    Array.prototype.forEach.call(selects, function (select) {
+        var column = +select.dataset.column;
+        var values = rows.map(function (row) { return text(row, column); })
+            .filter(function (value, i, all) { return value && all.indexOf(value) === i; })
+            .sort(function (a, b) { return a.localeCompare(b); });
+        values.forEach(function (value) {
+            var option = document.createElement("option");
+            option.textContent = value;
+            select.appendChild(option);
+        });
+        select.addEventListener("change", filter);
+    });
+    document.querySelector(".filters").hidden = false;
+
+    function filter() {
+        rows.forEach(function (row) {
+            row.hidden = Array.prototype.some.call(selects, function (select) {
+                return select.value && text(row, +select.dataset.column) !== select.value;
+            });
+        });
+    }
+
+    var sorted = { column: 0, descending: false };
+    Array.prototype.forEach.call(table.tHead.rows[0].cells, function (heading, column) {
+        heading.style.cursor = "pointer";
+        heading.title = "Sort by " + heading.textContent;
+        heading.addEventListener("click", function () {
+            sorted.descending = sorted.column === column && !sorted.descending;
+            sorted.column = column;
+            rows.sort(function (a, b) {
+                var order = text(a, column).localeCompare(text(b, column), undefined, { numeric: true })
+                    || text(a, 0).localeCompare(text(b, 0));
+                return sorted.descending ? -order : order;
+            });
+            rows.forEach(function (row) { body.appendChild(row); });
+        });
+    });
+})();
+"#;
+
+/// Writes the catalog as a JSON array of objects, one for each game.
+pub fn write_json(
+    writer: &mut impl Write,
+    site: &Site,
+    games: &[(&Page, Game)],
+) -> Res<()> {
+    writeln!(writer, "[")?;
+
+    for (i, (page, game)) in games.iter().enumerate() {
+        let player_count = match game.player_count() {
+            Some(count) => count.to_string(),
+            None => "null".to_owned(),
+        };
+
+        write!(
+            writer,
+            "  {{\"page\": {}, \"file\": {}, \"title\": {}, \"author\": {}, \
+            \"genre\": {}, \"players\": {}, \"player_count\": {}, \
+            \"status\": {}, \"quote\": {}, \"link\": {}, \
+            \"image\": {}, \"image_caption\": {}}}",
+            JsonString(&page.title),
+            JsonString(site.file_names.get(&page.title).unwrap_or("")),
+            JsonString(&game.title),
+            JsonString(&game.author),
+            JsonString(&game.genre),
+            JsonString(&game.players),
+            player_count,
+            JsonString(&game.status),
+            JsonString(&game.quote),
+            JsonString(&game.link),
+            JsonString(&game.image),
+            JsonString(&game.image_caption)
+        )?;
+
+        writeln!(writer, "{}", if i + 1 < games.len() { "," } else { "" })?;
+    }
+
+    writeln!(writer, "]")?;
+
+    Ok(())
+}
+
+/// Displays the string as a quoted JSON string.
+struct JsonString<'text>(&'text str);
+
+impl std::fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use std::fmt::Write;
+
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
@@ -0,0 +1,118 @@
+//! The `<noinclude>`, `<includeonly>` and `<onlyinclude>` tags, which let a
+//! page show different things when it is viewed and when it is transcluded
+//! into another page as a template.
+
+/// How the page is being used.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Use {
+    /// The page is being rendered as a page of its own.
+    Viewed,
+    /// The page is being used as a template by another page.
+    Transcluded,
+}
+
+/// Returns the parts of the text that apply to the given use, without the
+/// tags themselves, the way MediaWiki's preprocessor does it:
+///
+/// * `<noinclude>...</noinclude>` is only kept when the page is viewed.
+/// * `<includeonly>...</includeonly>` is only kept when it is transcluded.
+/// * When it is transcluded and there is any `<onlyinclude>...</onlyinclude>`,
+///   only what is inside those is kept.
+///
+/// An unclosed section runs to the end of the text. Comments, `<nowiki>`,
+/// `<pre>` and code sections are copied as they are, so the tags can be
+/// written about.
+pub fn apply(text: &str, use_: Use) -> String {
+    if use_ == Use::Transcluded && find_tag(text, "<onlyinclude>").is_some() {
+        let mut only_included = String::new();
+
+        let mut rest = text;
+        while let Some(start) = find_tag(rest, "<onlyinclude>") {
+            rest = &rest[start + "<onlyinclude>".len()..];
+
+            let end = find_tag(rest, "</onlyinclude>").unwrap_or(rest.len());
+            only_included.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        return strip(&only_included, use_)
+    }
+
+    strip(text, use_)
+}
+
+fn strip(text: &str, use_: Use) -> String {
+    let (kept, dropped) = match use_ {
+        Use::Viewed => ("noinclude", "includeonly"),
+        Use::Transcluded => ("includeonly", "noinclude"),
+    };
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('<') {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let verbatim_end = if rest.starts_with("<!--") {
+            Some(rest.find("-->").map_or(rest.len(), |end| end + "-->".len()))
+        } else {
+            ["nowiki", "pre", "syntaxhighlight", "source"].iter()
+                .find(|name| {
+                    starts_with_tag(rest, &format!("<{}>", name))
+                    || starts_with_tag(rest, &format!("<{} ", name))
+                })
+                .map(|name| {
+                    let end_tag = format!("</{}>", name);
+                    find_tag(rest, &end_tag).map_or(rest.len(), |end| end + end_tag.len())
+                })
+        };
+        if let Some(end) = verbatim_end {
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue
+        }
+
+        let dropped_open = format!("<{}>", dropped);
+        let dropped_close = format!("</{}>", dropped);
+
+        if starts_with_tag(rest, &dropped_open) {
+            rest = match find_tag(rest, &dropped_close) {
+                Some(end) => &rest[end + dropped_close.len()..],
+                None => "",
+            };
+            continue
+        }
+
+        let removed_tag = [
+            dropped_close,
+            format!("<{}>", kept),
+            format!("</{}>", kept),
+            "<onlyinclude>".to_owned(),
+            "</onlyinclude>".to_owned(),
+        ].iter().find(|tag| starts_with_tag(rest, tag)).map(|tag| tag.len());
+
+        match removed_tag {
+            Some(length) => rest = &rest[length..],
+            None => {
+                output.push('<');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    output.push_str(rest);
+
+    output
+}
+
+/// Tag names are case insensitive.
+pub fn starts_with_tag(text: &str, tag: &str) -> bool {
+    text.get(..tag.len()).is_some_and(|start| start.eq_ignore_ascii_case(tag))
+}
+
+pub fn find_tag(text: &str, tag: &str) -> Option<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| starts_with_tag(&text[i..], tag))
+}
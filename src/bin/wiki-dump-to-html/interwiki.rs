@@ -0,0 +1,138 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use super::{magic_words::url_encode, Res};
+
+/// The prefixes known without a map file, as the prefix, the URL with `$1`
+/// in place of the title, and the name of the site. `w` is Wikia's prefix
+/// for its central wiki, which is what the pages in the dump mean by it.
+const DEFAULT_PREFIXES: &[(&str, &str, &str)] = &[
+    ("wikipedia", "https://en.wikipedia.org/wiki/$1", "Wikipedia"),
+    ("wiktionary", "https://en.wiktionary.org/wiki/$1", "Wiktionary"),
+    ("commons", "https://commons.wikimedia.org/wiki/$1", "Wikimedia Commons"),
+    ("m", "https://meta.wikimedia.org/wiki/$1", "Meta-Wiki"),
+    ("meta", "https://meta.wikimedia.org/wiki/$1", "Meta-Wiki"),
+    ("mw", "https://www.mediawiki.org/wiki/$1", "MediaWiki.org"),
+    ("w", "https://community.fandom.com/wiki/$1", "Fandom Community Central"),
+    ("wikia", "https://community.fandom.com/wiki/$1", "Fandom Community Central"),
+];
+
+/// Maps the prefixes of interwiki links, like the `wikipedia` in
+/// `[[wikipedia:Lua]]`, to the sites they link to.
+pub struct Interwiki {
+    sites: HashMap<String, Wiki>,
+}
+
+struct Wiki {
+    url: String,
+    name: String,
+}
+
+/// Where an interwiki link points to.
+pub struct Link {
+    pub url: String,
+    /// The name of the site, followed by the title on that site.
+    pub description: String,
+}
+
+impl Default for Interwiki {
+    fn default() -> Self {
+        let mut interwiki = Interwiki {
+            sites: HashMap::new(),
+        };
+
+        for (prefix, url, name) in DEFAULT_PREFIXES {
+            interwiki.insert(prefix, url, name);
+        }
+
+        interwiki
+    }
+}
+
+impl Interwiki {
+    /// Returns the default map, with the prefixes from the file added in,
+    /// replacing any defaults with the same prefix. Each line of the file
+    /// has a prefix, a URL with `$1` where the title goes, and optionally a
+    /// name for the site, separated by whitespace. Empty lines and lines
+    /// starting with `#` are skipped.
+    pub fn load(path: &Path) -> Res<Self> {
+        let mut interwiki = Interwiki::default();
+
+        let text = fs::read_to_string(path)?;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let (prefix, url) = match (parts.next(), parts.next()) {
+                (Some(prefix), Some(url)) if url.contains("$1") => (prefix, url),
+                _ => {
+                    return Err(format!(
+                        "{}:{}: expected a prefix and a URL containing $1",
+                        path.display(),
+                        i + 1
+                    ).into())
+                },
+            };
+            let name = parts.next().map(str::trim).unwrap_or(prefix);
+
+            interwiki.insert(prefix, url, name);
+        }
+
+        Ok(interwiki)
+    }
+
+    fn insert(&mut self, prefix: &str, url: &str, name: &str) {
+        self.sites.insert(
+            prefix.to_lowercase(),
+            Wiki {
+                url: url.to_owned(),
+                name: name.to_owned(),
+            }
+        );
+    }
+
+    /// Returns whether the title starts with a known interwiki prefix.
+    pub fn is_interwiki(&self, title: &str) -> bool {
+        self.split(title).is_some()
+    }
+
+    /// Returns where the link target points to, if it starts with a known
+    /// interwiki prefix.
+    pub fn resolve(&self, target: &str) -> Option<Link> {
+        let (site, title) = self.split(target)?;
+
+        let (title, fragment) = match title.split_once('#') {
+            Some((title, fragment)) => (title, Some(fragment)),
+            None => (title, None),
+        };
+
+        let mut url = site.url.replace("$1", &url_encode(title.trim()));
+        if let Some(fragment) = fragment {
+            url.push('#');
+            url.push_str(&url_encode(fragment.trim()));
+        }
+
+        let description = if title.trim().is_empty() {
+            site.name.clone()
+        } else {
+            format!("{}: {}", site.name, title.trim())
+        };
+
+        Some(Link {
+            url,
+            description,
+        })
+    }
+
+    fn split<'title>(&self, title: &'title str) -> Option<(&Wiki, &'title str)> {
+        let title = title.trim().trim_start_matches(':');
+        let (prefix, rest) = title.split_once(':')?;
+
+        let site = self.sites.get(&prefix.trim().to_lowercase())?;
+
+        Some((site, rest))
+    }
+}
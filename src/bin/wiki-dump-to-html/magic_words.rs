@@ -0,0 +1,104 @@
+//! MediaWiki's variables, like `{{SITENAME}}` and `{{PAGENAME}}`, which look
+//! like templates, but are about the site or the page being rendered.
+
+use super::site_info::{Namespaces, SiteInfo};
+
+/// A title split into the key of its namespace and the rest of it.
+pub struct Title<'title> {
+    pub namespace: i32,
+    pub text: &'title str,
+}
+
+/// Returns the value of a variable about the site as a whole, or `None` if
+/// there is no such variable.
+pub fn site_variable(word: &str, site_info: &SiteInfo) -> Option<String> {
+    match word {
+        "SITENAME" => Some(site_info.site_name.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the value of a variable about the page with the given title,
+/// or `None` if there is no such variable. The variables ending in an extra
+/// `E` are the same as the ones without it, but encoded for use in URLs.
+pub fn title_variable(word: &str, title: &Title, namespaces: &Namespaces) -> Option<String> {
+    let (word, is_encoded) = match word {
+        "NAMESPACE" | "NAMESPACENUMBER" | "PAGENAME" | "FULLPAGENAME" | "BASEPAGENAME"
+        | "ROOTPAGENAME" | "SUBPAGENAME" | "TALKSPACE" | "SUBJECTSPACE" | "ARTICLESPACE"
+        | "TALKPAGENAME" | "SUBJECTPAGENAME" | "ARTICLEPAGENAME" => (word, false),
+        _ => match word.strip_suffix('E') {
+            Some(word) if word != "NAMESPACENUMBER" => (word, true),
+            _ => return None,
+        },
+    };
+
+    let namespace_name = |key: i32| match key {
+        0 => Some(""),
+        key => namespaces.name(key),
+    };
+    let prefixed = |key: i32, text: &str| match namespace_name(key) {
+        Some("") | None => text.to_owned(),
+        Some(name) => format!("{}:{}", name, text),
+    };
+
+    // Talk namespaces have odd keys, and follow the namespace they are about.
+    let talk_namespace = title.namespace | 1;
+    let subject_namespace = title.namespace & !1;
+
+    let text = title.text;
+    let has_subpages = has_subpages(title.namespace);
+
+    let value = match word {
+        "NAMESPACE" => namespace_name(title.namespace)?.to_owned(),
+        "NAMESPACENUMBER" => title.namespace.to_string(),
+        "PAGENAME" => text.to_owned(),
+        "FULLPAGENAME" => prefixed(title.namespace, text),
+        "BASEPAGENAME" if has_subpages => {
+            text.rsplit_once('/').map_or(text, |(base, _)| base).to_owned()
+        },
+        "ROOTPAGENAME" if has_subpages => {
+            text.split_once('/').map_or(text, |(root, _)| root).to_owned()
+        },
+        "SUBPAGENAME" if has_subpages => {
+            text.rsplit_once('/').map_or(text, |(_, sub)| sub).to_owned()
+        },
+        "BASEPAGENAME" | "ROOTPAGENAME" | "SUBPAGENAME" => text.to_owned(),
+        "TALKSPACE" => namespace_name(talk_namespace)?.to_owned(),
+        "SUBJECTSPACE" | "ARTICLESPACE" => namespace_name(subject_namespace)?.to_owned(),
+        "TALKPAGENAME" => prefixed(talk_namespace, text),
+        "SUBJECTPAGENAME" | "ARTICLEPAGENAME" => prefixed(subject_namespace, text),
+        _ => return None,
+    };
+
+    Some(if is_encoded { url_encode(&value) } else { value })
+}
+
+/// Returns whether titles in the namespace can have subpages, separated by
+/// slashes, going by MediaWiki's defaults.
+fn has_subpages(namespace: i32) -> bool {
+    const USER: i32 = 2;
+    const PROJECT: i32 = 4;
+    const TEMPLATE: i32 = 10;
+    const HELP: i32 = 12;
+
+    namespace % 2 == 1 || [USER, PROJECT, TEMPLATE, HELP].contains(&namespace)
+}
+
+/// Encodes the title for use in a URL, the way MediaWiki does it, with
+/// spaces as underscores, and a few more characters left alone than usual.
+pub fn url_encode(title: &str) -> String {
+    let mut encoded = String::with_capacity(title.len());
+
+    for byte in title.replace(' ', "_").bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9'
+            | b'-' | b'_' | b'.' | b'~'
+            | b';' | b':' | b'@' | b'$' | b'!' | b'*' | b'(' | b')' | b',' | b'/' => {
+                encoded.push(byte as char);
+            },
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
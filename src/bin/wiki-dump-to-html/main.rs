@@ -1,14 +1,56 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env::current_dir,
-    io::{BufWriter, Write},
+    io::{Read, Write},
     fs::{
         create_dir_all,
         File,
-        OpenOptions,
     },
     path::PathBuf,
 };
 
+mod definition_lists;
+
+mod expr;
+
+mod games;
+use games::{Game, GAMES_JSON_FILE_NAME, GAMES_TITLE};
+
+mod inclusion;
+
+mod interwiki;
+use interwiki::Interwiki;
+
+mod magic_words;
+
+mod output;
+use output::Output;
+
+mod overrides;
+use overrides::Overrides;
+
+mod parser_functions;
+
+mod reference;
+use reference::{Kind, Reference, CONSOLE_COMMANDS_TITLE};
+
+mod sanitizer;
+
+mod site_info;
+use site_info::SiteInfo;
+
+mod strip;
+use strip::StripMarkers;
+
+mod templates;
+use templates::Expander;
+
+mod toc;
+use toc::Toc;
+
+mod undocumented;
+use undocumented::UNDOCUMENTED_FEATURES_TITLE;
+
 type Res<A> = Result<A, Box<dyn std::error::Error>>; 
 
 const EXE_NAME: &str = "wiki-dump-to-html";
@@ -23,6 +65,10 @@ fn main() -> Res<()> {
 
     let mut verbose = false;
     let mut output_dir_spec = None;
+    let mut interwiki_map_spec = None;
+    let mut template_overrides_spec = None;
+    let mut include_ns_specs = Vec::new();
+    let mut exclude_ns_specs = Vec::new();
 
     let mut files = Vec::new();
 
@@ -45,6 +91,41 @@ fn main() -> Res<()> {
             continue;
         }
 
+        if s == "--interwiki-map" {
+            interwiki_map_spec = args.next();
+            if interwiki_map_spec.is_none() {
+                println!("Missing interwiki map file!");
+                return print_usage();
+            }
+            continue;
+        }
+
+        if s == "--template-overrides" {
+            template_overrides_spec = args.next();
+            if template_overrides_spec.is_none() {
+                println!("Missing template overrides dir!");
+                return print_usage();
+            }
+            continue;
+        }
+
+        if s == "--include-ns" || s == "--exclude-ns" {
+            let specs = if s == "--include-ns" {
+                &mut include_ns_specs
+            } else {
+                &mut exclude_ns_specs
+            };
+
+            match args.next() {
+                Some(spec) => specs.push(spec),
+                None => {
+                    println!("Missing namespace for {}!", s);
+                    return print_usage();
+                }
+            }
+            continue;
+        }
+
         let path = PathBuf::from(s);
 
         println!("found input file: {}", path.display());
@@ -70,29 +151,494 @@ fn main() -> Res<()> {
 
     println!("will output to {}", output_dir.display());
 
-    let mut pages = Vec::new();
+    let mut site_info = SiteInfo::default();
+    let mut all_pages = Vec::new();
 
     for file in files {
-        let new_pages = extract_pages(file, verbose)?;
-        pages.extend(new_pages.into_iter());
+        let (new_site_info, new_pages) = extract_pages(file)?;
+        site_info.merge(new_site_info);
+        all_pages.extend(new_pages);
+    }
+
+    let mut included_namespaces: BTreeSet<i32> = DEFAULT_NAMESPACES.iter()
+        .copied()
+        .collect();
+
+    for (specs, include) in [(&include_ns_specs, true), (&exclude_ns_specs, false)] {
+        for spec in specs.iter() {
+            let key = site_info.namespaces.key(spec).ok_or_else(|| {
+                let known: Vec<String> = site_info.namespaces.iter()
+                    .map(|(key, name)| format!("{} ({})", name, key))
+                    .collect();
+                format!(
+                    "Unknown namespace {:?}. The known namespaces are: {}",
+                    spec,
+                    known.join(", ")
+                )
+            })?;
+
+            if include {
+                included_namespaces.insert(key);
+            } else {
+                included_namespaces.remove(&key);
+            }
+        }
+    }
+
+    if verbose {
+        let names: Vec<&str> = included_namespaces.iter()
+            .map(|&key| site_info.namespaces.name(key).unwrap_or("?"))
+            .collect();
+        println!("including namespaces: {}", names.join(", "));
+    }
+
+    let config = parse_wiki_text::Configuration::default();
+
+    // Templates get used no matter which namespaces are included.
+    let interwiki = match interwiki_map_spec {
+        Some(path) => Interwiki::load(&PathBuf::from(path))?,
+        None => Interwiki::default(),
+    };
+
+    let overrides = match template_overrides_spec {
+        Some(path) => Overrides::load(&PathBuf::from(path))?,
+        None => Overrides::default(),
+    };
+
+    if verbose {
+        println!("using {} template overrides", overrides.len());
+    }
+
+    let markers = StripMarkers::default();
+
+    let expander = Expander::new(
+        &config,
+        &site_info,
+        &interwiki,
+        &overrides,
+        &markers,
+        all_pages.iter()
+    );
+
+    let mut pages = Vec::new();
+
+    for page in all_pages {
+        let namespace = page.namespace as i32;
+
+        if included_namespaces.contains(&namespace) {
+            if verbose {
+                println!(
+                    "The page {title:?} is included, with byte length {length} and namespace {namespace}.",
+                    title = page.title,
+                    length = page.text.len(),
+                    namespace = namespace
+                );
+            }
+
+            pages.push(page);
+        } else if verbose {
+            println!(
+                "Skipping the page {:?} since the {} namespace is not included.",
+                page.title,
+                site_info.namespaces.name(namespace).unwrap_or("unknown")
+            );
+        }
+    }
+
+    let mut site = Site::default();
+
+    let mut redirect_titles = Vec::new();
+    pages.retain(|page| {
+        match redirect_target(&config, &page.text) {
+            Some(target) => {
+                if verbose {
+                    println!("The page {:?} is a redirect to {:?}.", page.title, target);
+                }
+
+                site.redirects.insert(page.title.clone(), target);
+                redirect_titles.push(page.title.clone());
+
+                false
+            },
+            None => true,
+        }
+    });
+
+    for page in pages.iter_mut() {
+        let (text, diagnostics) = expander.expand_page(&page.title, &page.text);
+
+        for diagnostic in diagnostics {
+            eprintln!("While expanding templates on {:?}: {}", page.title, diagnostic);
+        }
+
+        page.text = definition_lists::indent_tables(&sanitizer::escape_stray_tags(&text));
+    }
+
+    site.interwiki = interwiki;
+    site.markers = markers;
+
+    let (category_pages, articles): (Vec<Page>, Vec<Page>) = pages
+        .into_iter()
+        .partition(|page| page.namespace == CATEGORY_NAMESPACE);
+
+    for page in articles.iter().chain(category_pages.iter()) {
+        let parsed = config.parse(&page.text);
+
+        for (category, sort_key) in page_categories(&page.text, &parsed.nodes) {
+            site.categories.entry(category).or_default().push(CategoryMember {
+                sort_key: sort_key.unwrap_or_else(|| page.title.clone()),
+                title: page.title.clone(),
+            });
+        }
+    }
+
+    for members in site.categories.values_mut() {
+        members.sort_by(|a, b| {
+            a.sort_key.to_uppercase().cmp(&b.sort_key.to_uppercase())
+                .then_with(|| a.title.cmp(&b.title))
+        });
+    }
+
+    // Categories that have members but no page of their own in the dump
+    // still get a generated page, with just the list of members.
+    let mut category_pages = category_pages;
+    for category in site.categories.keys() {
+        if !site.redirects.contains_key(category)
+        && !category_pages.iter().any(|page| &page.title == category) {
+            category_pages.push(Page {
+                format: None,
+                model: None,
+                namespace: CATEGORY_NAMESPACE,
+                text: String::new(),
+                title: category.clone(),
+            });
+        }
+    }
+    category_pages.sort_unstable_by(|a, b| a.title.cmp(&b.title));
+
+    let mut command_pages: Vec<&Page> = articles.iter()
+        .filter(|page| {
+            let parsed = config.parse(&page.text);
+            Reference::find(Kind::Command, &page.text, &parsed.nodes).is_some()
+        })
+        .collect();
+    command_pages.sort_unstable_by(|a, b| a.title.cmp(&b.title));
+
+    let mut games: Vec<(&Page, Game)> = articles.iter()
+        .filter_map(|page| {
+            let parsed = config.parse(&page.text);
+            Game::find(page, &parsed.nodes).map(|game| (page, game))
+        })
+        .collect();
+    games.sort_by(|(_, a), (_, b)| {
+        a.title.to_uppercase().cmp(&b.title.to_uppercase())
+    });
+
+    let mut marked_pages: Vec<(&Page, Vec<undocumented::Marker>)> = articles.iter()
+        .map(|page| {
+            let parsed = config.parse(&page.text);
+            (page, undocumented::find_markers(&page.text, &parsed.nodes))
+        })
+        .filter(|(_, markers)| !markers.is_empty())
+        .collect();
+    marked_pages.sort_by(|(a, _), (b, _)| a.title.cmp(&b.title));
+
+    // The pages we generate that aren't in the dump, by title.
+    let mut overviews = Vec::new();
+    if !command_pages.is_empty() {
+        overviews.push(CONSOLE_COMMANDS_TITLE);
+    }
+    if !games.is_empty() {
+        overviews.push(GAMES_TITLE);
+    }
+    if !marked_pages.is_empty() {
+        overviews.push(UNDOCUMENTED_FEATURES_TITLE);
+    }
+
+    // Articles get first pick of the file names, so the redirects are the
+    // ones that get a suffix if there is a collision.
+    for page in articles.iter().chain(category_pages.iter()) {
+        site.file_names.insert(&page.title);
+    }
+    for title in overviews.iter() {
+        site.file_names.insert_generated(title);
+    }
+    for title in redirect_titles.iter() {
+        site.file_names.insert(title);
+    }
+
+    let mut output = Output::new(output_dir)?;
+
+    for page in articles.iter().chain(category_pages.iter()) {
+        let file_name = site.file_names.get(&page.title)
+            .ok_or_else(|| format!("No file name for {:?}", page.title))?;
+
+        output.write_file(file_name, |writer| {
+            write_page(writer, &site, &config, page, verbose)
+        })?;
+    }
+
+    for title in redirect_titles.iter() {
+        let file_name = site.file_names.get(title)
+            .ok_or_else(|| format!("No file name for {:?}", title))?;
+
+        let href = match site.href(title) {
+            Some(href) => href,
+            None => {
+                if verbose {
+                    println!("Not writing a stub for {:?} since its target is missing.", title);
+                }
+                continue
+            }
+        };
+
+        output.write_file(file_name, |writer| {
+            write_header(
+                writer,
+                title,
+                &format!(
+                    "<meta http-equiv=\"refresh\" content=\"0; url={}\">",
+                    Escaped(&href)
+                )
+            )?;
+
+            write!(
+                writer,
+                "<p>{} redirects to <a href=\"{}\">{}</a>.</p></body></html>",
+                Escaped(title),
+                Escaped(&href),
+                Escaped(&site.redirects[title])
+            )?;
+
+            Ok(())
+        })?;
+    }
+
+    if !command_pages.is_empty() {
+        let file_name = site.file_names.get_generated(CONSOLE_COMMANDS_TITLE)
+            .ok_or("No file name for the console commands")?;
+
+        output.write_file(file_name, |writer| {
+            reference::write_console_commands(writer, &site, &config, &command_pages)
+        })?;
+    }
+
+    if !games.is_empty() {
+        let file_name = site.file_names.get_generated(GAMES_TITLE)
+            .ok_or("No file name for the games")?;
+
+        output.write_file(file_name, |writer| {
+            games::write_catalog(writer, &site, &games)
+        })?;
+
+        output.write_file(GAMES_JSON_FILE_NAME, |writer| {
+            games::write_json(writer, &site, &games)
+        })?;
     }
 
-    let mut index_path = output_dir.join("index");
-    index_path.set_extension("html");
+    if !marked_pages.is_empty() {
+        let file_name = site.file_names.get_generated(UNDOCUMENTED_FEATURES_TITLE)
+            .ok_or("No file name for the undocumented features")?;
+
+        output.write_file(file_name, |writer| {
+            undocumented::write_report(writer, &site, &marked_pages)
+        })?;
+    }
 
-    let index_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(index_path)?;
+    output.write_file(INDEX_FILE_NAME, |writer| {
+        write_index(writer, &site, &articles, &category_pages, &overviews)
+    })?;
 
-    let mut writer = BufWriter::new(&index_file);
+    output.commit(verbose)?;
+
+    Ok(())
+}
 
+fn write_index(
+    writer: &mut impl Write,
+    site: &Site,
+    articles: &[Page],
+    category_pages: &[Page],
+    overviews: &[&str],
+) -> Res<()> {
     macro_rules! w {
         ($($tokens: tt)*) => {
-            write!(&mut writer, $($tokens)*)?;
+            write!(writer, $($tokens)*)?;
+        }
+    }
+
+    write_header(writer, "Index", "")?;
+
+    w!("<h1>Index</h1>");
+
+    if !overviews.is_empty() {
+        w!("<nav class=\"overviews\"><ul>");
+        for title in overviews {
+            if let Some(file_name) = site.file_names.get_generated(title) {
+                w!("<li><a href=\"{}\">{}</a></li>", Escaped(file_name), Escaped(title));
+            }
+        }
+        w!("</ul></nav>");
+    }
+
+    let mut titles: Vec<&str> = articles.iter().map(|p| p.title.as_str()).collect();
+    titles.sort_unstable();
+
+    w!("<ul>");
+    for title in titles {
+        if let Some(file_name) = site.file_names.get(title) {
+            w!("<li><a href=\"{}\">{}</a></li>", Escaped(file_name), Escaped(title));
+        }
+    }
+    w!("</ul>");
+
+    w!("<h2>Categories</h2>");
+
+    w!("<ul>");
+    for page in category_pages.iter() {
+        if let Some(file_name) = site.file_names.get(&page.title) {
+            w!(
+                "<li><a href=\"{}\">{}</a></li>",
+                Escaped(file_name),
+                Escaped(category_name(&page.title))
+            );
+        }
+    }
+    w!("</ul>");
+
+    w!("</body></html>");
+
+    Ok(())
+}
+
+const INDEX_FILE_NAME: &str = "index.html";
+
+const CATEGORY_NAMESPACE: u32 = 14;
+
+/// The keys of the namespaces that hold actual content, as opposed to
+/// discussions, user pages, pages about the wiki itself, and so on. These
+/// keys are the same for every MediaWiki site: Main, and Category, which the
+/// category listings need.
+const DEFAULT_NAMESPACES: [i32; 2] = [0, CATEGORY_NAMESPACE as i32];
+
+fn write_page(
+    writer: &mut impl Write,
+    site: &Site,
+    config: &parse_wiki_text::Configuration,
+    page: &Page,
+    verbose: bool,
+) -> Res<()> {
+    macro_rules! w {
+        ($($tokens: tt)*) => {
+            write!(writer, $($tokens)*)?;
+        }
+    }
+
+    write_header(writer, &page.title, "")?;
+
+    w!("<nav><a href=\"{}\">Index</a></nav>", INDEX_FILE_NAME);
+    w!("<h1>{}</h1>", Escaped(&page.title));
+
+    let parsed = config.parse(&page.text);
+
+    if verbose && !parsed.warnings.is_empty() {
+        eprintln!("{:#?}", parsed.warnings);
+    }
+
+    let toc = Toc::new(&page.text, &parsed.nodes);
+
+    write_nodes(writer, site, &toc, &page.text, &parsed.nodes)?;
+
+    if page.namespace == CATEGORY_NAMESPACE {
+        write_category_listing(writer, site, &page.title)?;
+    }
+
+    let categories = page_categories(&page.text, &parsed.nodes);
+    if !categories.is_empty() {
+        w!("<footer class=\"categories\">Categories: ");
+        for (i, (category, _)) in categories.iter().enumerate() {
+            if i > 0 {
+                w!(" | ");
+            }
+            match site.href(category) {
+                Some(href) => {
+                    w!(
+                        "<a href=\"{}\">{}</a>",
+                        Escaped(&href),
+                        Escaped(category_name(category))
+                    );
+                },
+                None => {
+                    w!("{}", Escaped(category_name(category)));
+                },
+            }
+        }
+        w!("</footer>");
+    }
+
+    w!("</body></html>");
+
+    Ok(())
+}
+
+/// Writes the lists of subcategories and pages in the given category,
+/// grouped by the first letter of their sort keys, like MediaWiki does.
+fn write_category_listing(
+    writer: &mut impl Write,
+    site: &Site,
+    category: &str,
+) -> Res<()> {
+    let members = match site.categories.get(category) {
+        Some(members) => members,
+        None => return Ok(()),
+    };
+
+    let (subcategories, pages): (Vec<&CategoryMember>, Vec<&CategoryMember>) =
+        members.iter().partition(|member| member.title.starts_with("Category:"));
+
+    for (heading, members) in [("Subcategories", subcategories), ("Pages", pages)] {
+        if members.is_empty() {
+            continue
+        }
+
+        write!(
+            writer,
+            "<h2>{} in category \"{}\"</h2>",
+            heading,
+            Escaped(category_name(category))
+        )?;
+
+        let mut current_letter = None;
+        for member in members {
+            let letter = member.sort_key.trim().chars().next()
+                .map(|c| c.to_uppercase().collect::<String>());
+            if letter != current_letter {
+                if current_letter.is_some() {
+                    write!(writer, "</ul>")?;
+                }
+                write!(
+                    writer,
+                    "<h3>{}</h3><ul>",
+                    Escaped(letter.as_deref().unwrap_or(""))
+                )?;
+                current_letter = letter;
+            }
+
+            let file_name = site.file_names.get(&member.title).unwrap_or("");
+            write!(
+                writer,
+                "<li><a href=\"{}\">{}</a></li>",
+                Escaped(file_name),
+                Escaped(&member.title)
+            )?;
         }
+        write!(writer, "</ul>")?;
     }
 
+    Ok(())
+}
+
+fn write_header(writer: &mut impl Write, title: &str, extra_head: &str) -> Res<()> {
     let header = r##"<!DOCTYPE html>
 <html><head>
 <meta http-equiv="content-type" content="text/html; charset=UTF-8"><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><style type="text/css">body{
@@ -107,41 +653,550 @@ padding:0 10px
 h1,h2,h3{line-height:1.2}
 a:link {color: #999;}
 a:visited {color: #666;}
+a.new {color: #c44;}
+a.external::after {
+    content: "\2197";
+    font-size: 75%;
+    vertical-align: super;
+}
+dt {font-weight: bold;}
+dd {margin-left: 1.6em;}
+.toc {
+    display: inline-block;
+    border: 1px solid #444;
+    padding: 0 1em;
+}
+.toc h2 {font-size: 100%;}
+.toc ul {list-style: none; padding-left: 1em;}
 pre {
     background-color:#1D2B53;
     color: #aaa;
 }
-</style></head>
-<body>"##;
+.reference .signature {font-size: 160%; margin: 1em 0;}
+.reference table {border-collapse: collapse;}
+.reference th, .reference td {
+    border-top: 1px solid #444;
+    padding: 0.25em 0.5em;
+    text-align: left;
+    vertical-align: top;
+}
+.reference th {white-space: nowrap;}
+.reference.command .signature {
+    border-left: 4px solid #FF004D;
+    padding-left: 0.5em;
+}
+.reference.command .signature::before {content: "> "; color: #666;}
+.infobox {
+    float: right;
+    margin: 0 0 1em 1em;
+    border: 1px solid #444;
+}
+.infobox th, .infobox td {padding: 0.1em 0.5em; text-align: left;}
+.infobox .infobox-header {text-align: center; font-size: 120%;}
+.infobox .infobox-caption {text-align: center; font-size: 80%;}
+.callout {
+    border: 1px solid #999;
+    padding: 0.5em;
+    margin-bottom: 1em;
+}
+.callout.warning {border-color: #FFA300; border-left-width: 6px;}
+.callout-title {font-weight: bold; color: #FFA300;}
+table.catalog {width: 100%; border-collapse: collapse;}
+table.catalog th, table.catalog td {
+    border-bottom: 1px solid #444;
+    padding: 0.25em 0.5em;
+    text-align: left;
+}
+table.wikitable, table.article-table {
+    border-collapse: collapse;
+    margin: 1em 0;
+}
+.wikitable th, .wikitable td, .article-table th, .article-table td {
+    border: 1px solid #444;
+    padding: 0.2em 0.4em;
+}
+.wikitable th, .article-table th {background-color: #1D2B53;}
+.optional {
+    font-size: 70%;
+    font-weight: normal;
+    border: 1px solid #666;
+    border-radius: 0.5em;
+    padding: 0 0.4em;
+}
+</style>"##;
 
-    w!("{}", header);
+    write!(
+        writer,
+        "{}<title>{}</title>{}</head>\n<body>",
+        header,
+        Escaped(title),
+        extra_head
+    )?;
 
-    
-    let config = parse_wiki_text::Configuration::default();
-    
+    Ok(())
+}
 
-    for page in pages.iter() {
-        w!("<h2>{}</h2>", &page.title);
+/// Everything about the wiki as a whole that is needed to render a page.
+#[derive(Default)]
+struct Site {
+    file_names: FileNames,
+    /// Maps the title of each redirect page to the target as written.
+    redirects: HashMap<String, String>,
+    /// Maps the title of each category to its members, sorted for display.
+    categories: BTreeMap<String, Vec<CategoryMember>>,
+    interwiki: Interwiki,
+    /// For the HTML that the markers left in the page text stand for.
+    markers: StripMarkers,
+}
 
-        let parsed = config.parse(&page.text);
+struct CategoryMember {
+    sort_key: String,
+    title: String,
+}
+
+impl Site {
+    /// Returns the relative URL of the page that a `[[target]]` link points
+    /// to, or `None` if the page is not part of the output.
+    fn href(&self, target: &str) -> Option<String> {
+        let (title, mut fragment) = split_target(target);
+
+        let mut href = if title.is_empty() {
+            // `[[#Section]]` links to the current page.
+            String::new()
+        } else {
+            let (title, redirect_fragment) = self.resolve_redirects(title);
+            // A fragment on the link itself wins over one on the redirect.
+            fragment = fragment.or(redirect_fragment);
 
-        if verbose && parsed.warnings.len() > 0 {
-            eprintln!("{:#?}", parsed.warnings);
+            self.file_names.get(&title)?.to_owned()
+        };
+
+        if let Some(fragment) = fragment {
+            href.push('#');
+            href.push_str(&section_anchor(fragment));
         }
 
-        write_nodes(&mut writer, &page.text, &parsed.nodes)?;
+        Some(href)
+    }
+
+    /// Follows redirects starting from the given title, returning the title
+    /// of the page that is finally landed on, along with the fragment of the
+    /// last redirect that had one.
+    fn resolve_redirects(&self, title: String) -> (String, Option<&str>) {
+        // MediaWiki itself only follows a single redirect, but there's no
+        // harm in being more lenient, as long as loops are handled.
+        const MAX_HOPS: usize = 8;
 
-        w!("<hr style=\"height: 0.0625em;background-color: #888;\" />");
+        let mut title = title;
+        let mut fragment = None;
+
+        for _ in 0..MAX_HOPS {
+            match self.redirects.get(&title) {
+                Some(target) => {
+                    let (next_title, next_fragment) = split_target(target);
+                    if next_title.is_empty() {
+                        break
+                    }
+                    title = next_title;
+                    fragment = next_fragment.or(fragment);
+                },
+                None => break,
+            }
+        }
+
+        (title, fragment)
     }
-    
-    w!("</body></html>");
+}
 
-    Ok(())
+/// Splits a link target like `:Category:Page name#Section` into the
+/// normalized title and the fragment, if any. The title is empty for links
+/// to sections of the current page.
+fn split_target(target: &str) -> (String, Option<&str>) {
+    let target = target.trim().trim_start_matches(':');
+
+    match target.find('#') {
+        Some(i) => (normalize_title(&target[..i]), Some(&target[i + 1..])),
+        None => (normalize_title(target), None),
+    }
+}
+
+/// Returns the title of each category the page is in, in the order they
+/// appear, along with the sort key given for the page, if any.
+fn page_categories(
+    page_text: &str,
+    nodes: &[Node]
+) -> Vec<(String, Option<String>)> {
+    use parse_wiki_text::Positioned;
+
+    let mut categories: Vec<(String, Option<String>)> = Vec::new();
+
+    for_each_node(nodes, &mut |node| {
+        if let Node::Category { target, ordinal, .. } = node {
+            let title = category_title(target);
+
+            if categories.iter().any(|(t, _)| t == &title) {
+                return
+            }
+
+            let sort_key = match (ordinal.first(), ordinal.last()) {
+                (Some(first), Some(last)) => Some(
+                    page_text[first.start()..last.end()].to_owned()
+                ),
+                _ => None,
+            };
+
+            categories.push((title, sort_key));
+        }
+    });
+
+    categories
+}
+
+/// Returns the normalized title of the page for the category named in a
+/// `[[Category:Name]]` link, which may be written with any capitalization
+/// of "Category" and with extra spaces.
+fn category_title(target: &str) -> String {
+    let name = match target.find(':') {
+        Some(i) => &target[i + 1..],
+        None => target,
+    };
+
+    format!("Category:{}", normalize_title(name))
+}
+
+/// Returns the name of the category without the namespace prefix.
+fn category_name(title: &str) -> &str {
+    title.strip_prefix("Category:").unwrap_or(title)
+}
+
+/// Calls `f` on each of the nodes, and all the nodes nested inside them.
+fn for_each_node<'node>(nodes: &[Node<'node>], f: &mut impl FnMut(&Node<'node>)) {
+    for node in nodes {
+        f(node);
+
+        for children in child_lists(node) {
+            for_each_node(children, f);
+        }
+    }
+}
+
+/// Returns the lists of nodes directly nested inside the node, in the order
+/// they appear in the text.
+fn child_lists<'a, 'node>(node: &'a Node<'node>) -> Vec<&'a [Node<'node>]> {
+    let mut lists: Vec<&[Node]> = Vec::new();
+
+    use Node::*;
+    match node {
+        Category { ordinal: nodes, .. }
+        | ExternalLink { nodes, .. }
+        | Heading { nodes, .. }
+        | Image { text: nodes, .. }
+        | Link { text: nodes, .. }
+        | Preformatted { nodes, .. }
+        | Tag { nodes, .. } => lists.push(nodes),
+        DefinitionList { items, .. } => {
+            for item in items {
+                lists.push(&item.nodes);
+            }
+        },
+        OrderedList { items, .. }
+        | UnorderedList { items, .. } => {
+            for item in items {
+                lists.push(&item.nodes);
+            }
+        },
+        Parameter { name, default, .. } => {
+            lists.push(name);
+            if let Some(default) = default {
+                lists.push(default);
+            }
+        },
+        Table { attributes, captions, rows, .. } => {
+            lists.push(attributes);
+            for caption in captions {
+                if let Some(attributes) = &caption.attributes {
+                    lists.push(attributes);
+                }
+                lists.push(&caption.content);
+            }
+            for row in rows {
+                lists.push(&row.attributes);
+                for cell in row.cells.iter() {
+                    if let Some(attributes) = &cell.attributes {
+                        lists.push(attributes);
+                    }
+                    lists.push(&cell.content);
+                }
+            }
+        },
+        Template { name, parameters, .. } => {
+            lists.push(name);
+            for parameter in parameters {
+                if let Some(name) = &parameter.name {
+                    lists.push(name);
+                }
+                lists.push(&parameter.value);
+            }
+        },
+        Bold { .. }
+        | BoldItalic { .. }
+        | CharacterEntity { .. }
+        | Comment { .. }
+        | EndTag { .. }
+        | HorizontalDivider { .. }
+        | Italic { .. }
+        | MagicWord { .. }
+        | ParagraphBreak { .. }
+        | Redirect { .. }
+        | StartTag { .. }
+        | Text { .. } => {},
+    }
+
+    lists
+}
+
+/// Returns the text the nodes were parsed from, or `None` if there are no
+/// nodes.
+fn nodes_source<'text>(text: &'text str, nodes: &[Node]) -> Option<&'text str> {
+    use parse_wiki_text::Positioned;
+
+    match (nodes.first(), nodes.last()) {
+        (Some(first), Some(last)) => Some(&text[first.start()..source_end(last)]),
+        _ => None,
+    }
+}
+
+/// Returns the position in the text where the node ends. This is the same
+/// as `Positioned::end`, except the parser reports `{{{parameter}}}` nodes
+/// as ending before the closing braces, so we correct for that here.
+fn source_end(node: &Node) -> usize {
+    use parse_wiki_text::Positioned;
+
+    match node {
+        Node::Parameter { .. } => node.end() + "}}}".len(),
+        _ => node.end(),
+    }
+}
+
+/// Returns the target of the page if the page is a `#REDIRECT`.
+fn redirect_target(
+    config: &parse_wiki_text::Configuration,
+    text: &str
+) -> Option<String> {
+    config.parse(text).nodes.iter().find_map(|node| match node {
+        Node::Redirect { target, .. } => Some(target.to_string()),
+        _ => None,
+    })
+}
+
+/// Converts a title as written in a link into the form used in the dump:
+/// underscores become spaces, runs of whitespace are collapsed and the
+/// first letter is capitalized.
+fn normalize_title(title: &str) -> String {
+    let mut normalized = String::with_capacity(title.len());
+
+    for word in title.split(|c: char| c == '_' || c.is_whitespace())
+        .filter(|w| !w.is_empty()) {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.push_str(word);
+    }
+
+    let mut chars = normalized.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => normalized,
+    }
+}
+
+/// Encodes a section name the way MediaWiki does for its heading anchors,
+/// so `[[Page#Some section]]` style links land in the right place.
+fn section_anchor(section: &str) -> String {
+    let mut anchor = String::with_capacity(section.len());
+
+    for c in section.trim().chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9'
+            | '-' | '.' | ':' | '_' => anchor.push(c),
+            ' ' => anchor.push('_'),
+            _ => {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    anchor.push_str(&format!(".{:02X}", byte));
+                }
+            }
+        }
+    }
+
+    anchor
+}
+
+/// Escapes the wrapped text when displayed, so it can be used in HTML, as
+/// either text or a double-quoted attribute value. Everything from the dump
+/// should go through this, so only markup we produce on purpose is emitted.
+struct Escaped<'text>(&'text str);
+
+impl std::fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut rest = self.0;
+
+        while let Some(i) = rest.find(['&', '<', '>', '"', '\'']) {
+            f.write_str(&rest[..i])?;
+            f.write_str(match rest.as_bytes()[i] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'"' => "&quot;",
+                _ => "&#39;",
+            })?;
+            rest = &rest[i + 1..];
+        }
+
+        f.write_str(rest)
+    }
+}
+
+/// Returns the text of the nodes with the markup removed, as used for
+/// things like heading anchors.
+fn plain_text(page_text: &str, nodes: &[Node]) -> String {
+    use parse_wiki_text::Positioned;
+
+    let mut text = String::new();
+
+    for node in nodes {
+        match node {
+            Node::Text { value, .. } => text.push_str(&strip::remove_markers(value)),
+            Node::CharacterEntity { character, .. } => text.push(*character),
+            Node::Link { text: nodes, .. }
+            | Node::ExternalLink { nodes, .. }
+            | Node::Tag { nodes, .. } => {
+                text.push_str(&plain_text(page_text, nodes));
+            },
+            Node::Template { .. } => {
+                text.push_str(&page_text[node.start()..node.end()]);
+            },
+            _ => {},
+        }
+    }
+
+    text.trim().to_owned()
+}
+
+/// Returns the normalized name of a template, from the nodes the parser
+/// gives for it.
+fn template_name(page_text: &str, name: &[Node]) -> String {
+    normalize_title(&plain_text(page_text, name))
+}
+
+/// Keeps track of which file each page is written to, making sure that no
+/// two pages end up in the same file, even on case-insensitive filesystems.
+#[derive(Default)]
+struct FileNames {
+    by_title: HashMap<String, String>,
+    /// The pages we generate, by title, which are kept apart from the pages
+    /// in the dump, since the dump could have pages with the same titles.
+    generated_by_title: HashMap<String, String>,
+    // Lowercased, so we can detect collisions on case-insensitive filesystems.
+    taken: HashSet<String>,
+}
+
+impl FileNames {
+    fn insert(&mut self, title: &str) -> &str {
+        if !self.by_title.contains_key(title) {
+            let file_name = self.take(title);
+            self.by_title.insert(title.to_owned(), file_name);
+        }
+
+        &self.by_title[title]
+    }
+
+    fn get(&self, title: &str) -> Option<&str> {
+        self.by_title.get(title).map(|s| s.as_str())
+    }
+
+    /// Like `insert`, for a page that we generate.
+    fn insert_generated(&mut self, title: &str) -> &str {
+        if !self.generated_by_title.contains_key(title) {
+            let file_name = self.take(title);
+            self.generated_by_title.insert(title.to_owned(), file_name);
+        }
+
+        &self.generated_by_title[title]
+    }
+
+    /// Like `get`, for a page that we generate.
+    fn get_generated(&self, title: &str) -> Option<&str> {
+        self.generated_by_title.get(title).map(|s| s.as_str())
+    }
+
+    /// Returns a file name for the title that no other page has yet, and
+    /// marks it as taken.
+    fn take(&mut self, title: &str) -> String {
+        let stem = file_stem_for_title(title);
+
+        let mut file_name = format!("{}.html", stem);
+        let mut counter = 2;
+        while file_name.eq_ignore_ascii_case(INDEX_FILE_NAME)
+        || self.taken.contains(&file_name.to_ascii_lowercase()) {
+            file_name = format!("{}~{}.html", stem, counter);
+            counter += 1;
+        }
+
+        self.taken.insert(file_name.to_ascii_lowercase());
+
+        file_name
+    }
+}
+
+/// Returns a file name stem, (that is without the extension,) which is
+/// safe to use on all common filesystems, and also safe to use in a URL
+/// without further encoding. Different titles can produce the same stem,
+/// (for example, because of truncation,) so `FileNames` handles that.
+fn file_stem_for_title(title: &str) -> String {
+    const MAX_STEM_LEN: usize = 100;
+
+    let mut stem = String::with_capacity(title.len());
+
+    for (i, c) in title.chars().enumerate() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9'
+            | '-' | '(' | ')' | ',' | '!' => stem.push(c),
+            ' ' | '_' => stem.push('_'),
+            // A leading dot would make a hidden file on unix-likes.
+            '.' if i > 0 => stem.push(c),
+            _ => {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    stem.push_str(&format!("~{:02X}", byte));
+                }
+            }
+        }
+    }
+
+    // Everything pushed above is ASCII, so any index is a char boundary.
+    stem.truncate(MAX_STEM_LEN);
+
+    // Windows reserves these names, regardless of extension.
+    const RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL",
+        "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+        "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    if stem.is_empty()
+    || RESERVED.iter().any(|r| r.eq_ignore_ascii_case(&stem)) {
+        stem.push('_');
+    }
+
+    stem
 }
 
 use parse_wiki_text::Node;
 fn write_nodes<'node>(
-    writer: &mut BufWriter<&File>, 
+    writer: &mut impl Write,
+    site: &Site,
+    toc: &Toc,
     page_text: &str,
     nodes: &[Node<'node>]
 ) -> Res<()> {
@@ -158,6 +1213,9 @@ fn write_nodes<'node>(
     let mut is_bold_italic_open = false;
     let mut is_italic_open = false;
 
+    // The HTML tags that were opened in these nodes, and are still open.
+    let mut open_tags: Vec<&str> = Vec::new();
+
     for node in nodes.iter() {
         use Node::*;
 
@@ -167,18 +1225,31 @@ fn write_nodes<'node>(
                 ..
             } => {
                 w!("<pre>");
-                write_nodes(writer, page_text, nodes)?;
+                write_nodes(writer, site, toc, page_text, nodes)?;
                 w!("</pre>");
             },
             Heading {
                 level,
                 nodes,
+                start,
                 ..
             } => {
-                // we use h2 for the titles.
-                let l = level + 2;
-                w!("<h{}>", l);
-                write_nodes(writer, page_text, nodes)?;
+                if toc.goes_before_heading(*start) {
+                    toc.write(writer)?;
+                }
+
+                // We use h1 for the titles, which conveniently matches
+                // `= Title =` being level 1.
+                let l = (*level).clamp(1, 6);
+                match toc.anchor(*start) {
+                    Some(anchor) => {
+                        w!("<h{} id=\"{}\">", l, Escaped(anchor));
+                    },
+                    None => {
+                        w!("<h{}>", l);
+                    },
+                }
+                write_nodes(writer, site, toc, page_text, nodes)?;
                 w!("</h{}>", l);
             },
             HorizontalDivider {..} => {
@@ -208,20 +1279,6 @@ fn write_nodes<'node>(
                     w!("</span>");
                 }
             },
-            Tag {
-                name,
-                nodes,
-                ..
-            } if name == "syntaxhighlight" => {
-                for node in nodes {
-                    w!("<pre>");
-                    w!(
-                        "{}", 
-                        &page_text[node.start()..node.end()]
-                    );
-                    w!("</pre>");
-                }
-            },
             OrderedList {
                 items,
                 ..
@@ -229,11 +1286,47 @@ fn write_nodes<'node>(
                 w!("<ol>");
                 for item in items {
                     w!("<li>");
-                    write_nodes(writer, page_text, &item.nodes)?;
+                    write_nodes(writer, site, toc, page_text, &item.nodes)?;
                     w!("</li>");
                 }
                 w!("</ol>");
             },
+            DefinitionList {
+                items,
+                ..
+            } => {
+                use parse_wiki_text::DefinitionListItemType;
+
+                w!("<dl>");
+                for item in items {
+                    match item.type_ {
+                        DefinitionListItemType::Term => {
+                            w!("<dt>");
+                            match definition_lists::split_term(&item.nodes) {
+                                // `; term : definition` on a single line.
+                                Some(split) => {
+                                    write_nodes(writer, site, toc, page_text, split.term)?;
+                                    site.markers.write_text(writer, split.term_end)?;
+                                    w!("</dt><dd>");
+                                    site.markers.write_text(writer, split.definition_start)?;
+                                    write_nodes(writer, site, toc, page_text, split.definition)?;
+                                    w!("</dd>");
+                                },
+                                None => {
+                                    write_nodes(writer, site, toc, page_text, &item.nodes)?;
+                                    w!("</dt>");
+                                },
+                            }
+                        },
+                        DefinitionListItemType::Details => {
+                            w!("<dd>");
+                            write_nodes(writer, site, toc, page_text, &item.nodes)?;
+                            w!("</dd>");
+                        },
+                    }
+                }
+                w!("</dl>");
+            },
             UnorderedList {
                 items,
                 ..
@@ -241,21 +1334,249 @@ fn write_nodes<'node>(
                 w!("<ul>");
                 for item in items {
                     w!("<li>");
-                    write_nodes(writer, page_text, &item.nodes)?;
+                    write_nodes(writer, site, toc, page_text, &item.nodes)?;
                     w!("</li>");
                 }
                 w!("</ul>");
             },
-            Category{..} => {},
-            _ => {
+            Link {
+                target,
+                text,
+                ..
+            } => {
+                match (site.interwiki.resolve(target), site.href(target)) {
+                    // Interwiki prefixes win, like in MediaWiki, where a
+                    // page can't have a title starting with one.
+                    (Some(link), _) => {
+                        w!(
+                            "<a class=\"external interwiki\" href=\"{}\" title=\"{}, an off-site link which needs a network connection\">",
+                            Escaped(&link.url),
+                            Escaped(&link.description)
+                        );
+                    },
+                    (None, Some(href)) => {
+                        w!("<a href=\"{}\">", Escaped(&href));
+                    },
+                    (None, None) => {
+                        w!(
+                            "<a class=\"new\" title=\"{} (page does not exist)\">",
+                            Escaped(target.trim_start_matches(':'))
+                        );
+                    },
+                }
+
+                match text.split_first() {
+                    // Unlabelled colon links are displayed without the colon.
+                    Some((Text { value, .. }, rest)) if value == target => {
+                        site.markers.write_text(writer, value.trim_start_matches(':'))?;
+                        write_nodes(writer, site, toc, page_text, rest)?;
+                    },
+                    _ => {
+                        write_nodes(writer, site, toc, page_text, text)?;
+                    }
+                }
+
+                w!("</a>");
+            },
+            ExternalLink {
+                nodes,
+                ..
+            } => {
+                let (url, label_start, rest) = match nodes.split_first() {
+                    Some((Text { value, .. }, rest)) => {
+                        let value = value.trim_start();
+                        match value.find(char::is_whitespace) {
+                            Some(i) => (&value[..i], value[i..].trim_start(), rest),
+                            None => (value, "", rest),
+                        }
+                    },
+                    _ => ("", "", &nodes[..]),
+                };
+
                 w!(
-                    "{}", 
-                    &page_text[node.start()..node.end()]
+                    "<a class=\"external\" href=\"{0}\" title=\"Off-site link to {0}, which needs a network connection\">",
+                    Escaped(url)
                 );
+
+                if label_start.is_empty() && rest.is_empty() {
+                    w!("{}", Escaped(url));
+                } else {
+                    site.markers.write_text(writer, label_start)?;
+                    write_nodes(writer, site, toc, page_text, rest)?;
+                }
+
+                w!("</a>");
+            },
+            MagicWord {..} => {
+                let word = page_text[node.start()..node.end()].trim_matches('_');
+                if word.eq_ignore_ascii_case("TOC") && toc.goes_at_magic_word() {
+                    toc.write(writer)?;
+                }
+            },
+            Text {
+                value,
+                ..
+            } => {
+                site.markers.write_text(writer, value)?;
+            },
+            CharacterEntity {
+                character,
+                ..
+            } => {
+                let mut buffer = [0; 4];
+                w!("{}", Escaped(character.encode_utf8(&mut buffer)));
+            },
+            StartTag {
+                name,
+                ..
+            } => {
+                let source = &page_text[node.start()..node.end()];
+
+                if sanitizer::is_well_formed_tag(source) && sanitizer::is_allowed_element(name) {
+                    w!("<{}", name);
+                    sanitizer::write_attributes(writer, name, sanitizer::tag_attributes(source))?;
+                    w!(">");
+
+                    if sanitizer::is_void_element(name) {
+                        // Nothing to close.
+                    } else if source.ends_with("/>") {
+                        // MediaWiki takes `<span/>` to mean `<span></span>`.
+                        w!("</{}>", name);
+                    } else {
+                        open_tags.push(name);
+                    }
+                } else {
+                    site.markers.write_text(writer, source)?;
+                }
+            },
+            EndTag {
+                name,
+                ..
+            } if sanitizer::is_well_formed_tag(&page_text[node.start()..node.end()]) => {
+                // Closing a tag closes the ones opened inside it, which were
+                // left open, so the page stays balanced.
+                match open_tags.iter().rposition(|open| open == name) {
+                    Some(i) => {
+                        for open in open_tags.drain(i..).rev() {
+                            w!("</{}>", open);
+                        }
+                    },
+                    // Browsers take `</br>` to mean `<br>`, and so does
+                    // MediaWiki.
+                    None if name == "br" => {
+                        w!("<br>");
+                    },
+                    None => {
+                        site.markers.write_text(writer, &page_text[node.start()..node.end()])?;
+                    },
+                }
+            },
+            // `<nowiki>`, `<pre>` and `<syntaxhighlight>` never get here,
+            // since they are replaced with strip markers before parsing.
+            Tag {
+                nodes,
+                ..
+            } => {
+                write_nodes(writer, site, toc, page_text, nodes)?;
+            },
+            Template {
+                name,
+                parameters,
+                ..
+            } => {
+                let name = template_name(page_text, name);
+
+                match Kind::from_template_name(&name) {
+                    Some(kind) => {
+                        Reference::new(kind, page_text, parameters)
+                            .write(writer, site, toc, page_text)?;
+                    },
+                    None if name == games::GAME => {
+                        Game::new(page_text, parameters).write_infobox(writer)?;
+                    },
+                    None if name == undocumented::UNDOCUMENTED_FEATURE => {
+                        undocumented::write_callout(writer)?;
+                    },
+                    None => {
+                        site.markers.write_text(writer, &page_text[node.start()..node.end()])?;
+                    },
+                }
+            },
+            Table {
+                attributes,
+                captions,
+                rows,
+                ..
+            } => {
+                let attributes_source = |attributes: &[Node]| {
+                    nodes_source(page_text, attributes).unwrap_or("")
+                };
+
+                w!("<table");
+                sanitizer::write_attributes(writer, "table", attributes_source(attributes))?;
+                w!(">");
+
+                for caption in captions {
+                    w!("<caption");
+                    if let Some(attributes) = &caption.attributes {
+                        sanitizer::write_attributes(
+                            writer,
+                            "caption",
+                            attributes_source(attributes)
+                        )?;
+                    }
+                    w!(">");
+                    write_nodes(writer, site, toc, page_text, &caption.content)?;
+                    w!("</caption>");
+                }
+
+                for row in rows {
+                    // Like MediaWiki, we leave out rows without cells, as
+                    // there is before a `|-` right at the start of a table.
+                    if row.cells.is_empty() {
+                        continue
+                    }
+
+                    w!("<tr");
+                    sanitizer::write_attributes(writer, "tr", attributes_source(&row.attributes))?;
+                    w!(">");
+
+                    for cell in row.cells.iter() {
+                        let element = match cell.type_ {
+                            parse_wiki_text::TableCellType::Heading => "th",
+                            parse_wiki_text::TableCellType::Ordinary => "td",
+                        };
+
+                        w!("<{}", element);
+                        if let Some(attributes) = &cell.attributes {
+                            sanitizer::write_attributes(
+                                writer,
+                                element,
+                                attributes_source(attributes)
+                            )?;
+                        }
+                        w!(">");
+                        write_nodes(writer, site, toc, page_text, &cell.content)?;
+                        w!("</{}>", element);
+                    }
+
+                    w!("</tr>");
+                }
+
+                w!("</table>");
+            },
+            Category{..}
+            | Comment{..} => {},
+            _ => {
+                site.markers.write_text(writer, &page_text[node.start()..source_end(node)])?;
             }
         }
     }
 
+    for open in open_tags.iter().rev() {
+        w!("</{}>", open);
+    }
+
     Ok(())
 }
 
@@ -283,82 +1604,48 @@ fn confirm_out_dir(path: PathBuf) -> Res<PathBuf> {
 
 type Page = parse_mediawiki_dump::Page;
 
-fn extract_pages(file: File, verbose: bool) -> Res<Vec<Page>> {
-    let file = std::io::BufReader::new(file);
+/// Reads the site info and every page from the dump.
+fn extract_pages(file: File) -> Res<(SiteInfo, Vec<Page>)> {
+    let mut dump = String::new();
+    std::io::BufReader::new(file).read_to_string(&mut dump)?;
+
+    let site_info = SiteInfo::parse(&dump)?;
 
     let mut pages = Vec::new();
 
-    for result in parse_mediawiki_dump::parse(file) {
+    for result in parse_mediawiki_dump::parse(dump.as_bytes()) {
         let page = result.map_err(|e| e.to_string())?;
 
-        type Namespace = u32;
-        const TALK: Namespace = 1;
-        const USER: Namespace = 2;
-        const USER_TALK: Namespace = 3;
-        const FILE: Namespace = 6;
-        const MEDIA_WIKI: Namespace = 8;
-
-        const TEMPLATE: Namespace = 10;
-        const CATEGORY: Namespace = 14;
-        const CATEGORY_TALK: Namespace = 15;
-
-        //const USER_BLOG: Namespace = 500;
-        const USER_BLOG_COMMENT: Namespace = 501;
-        const BLOG: Namespace = 502;
-
-        const MESSAGE_WALL: Namespace = 1200;
-        const THREAD: Namespace = 1201;
-        const MESSAGE_WALL_GREETING: Namespace = 1202;
-
-        const BOARD: Namespace = 2000;
-
-        match page.namespace {
-            TALK
-            | USER
-            | USER_TALK
-            | MESSAGE_WALL
-            | THREAD
-            | MESSAGE_WALL_GREETING
-            | USER_BLOG_COMMENT
-            | TEMPLATE
-            | CATEGORY
-            | CATEGORY_TALK
-            | BOARD
-            | MEDIA_WIKI
-            | BLOG => {
-                if verbose {
-                    println!("Seems like the page {:?} is meta-content, not true content.", page.title);
-                    println!("{:#?}", page);
-                }
-            }
-            FILE => {
-                if verbose {
-                    println!("The page {:?} seems to be a file which we are skipping for now.", page.title);
-                    println!("{:#?}", page);
-                }
-            }
-            _ => {
-                if verbose {
-                    println!(
-                        "The page {title:?} seems to be a content article with byte length {length} and namespace {namespace}.",
-                        title = page.title,
-                        length = page.text.len(),
-                        namespace = page.namespace
-                    );
-                }
-
-                pages.push(page);
-            }
-        }
+        pages.push(page);
     }
 
-    Ok(pages)
+    Ok((site_info, pages))
 }
 
 fn print_usage() -> Res<()> {
     println!(
-        "USAGE: {} [--verbose] [--output-dir DIRNAME] FILENAME1 [FILENAME2 [...]]",
+        "USAGE: {} [--verbose] [--output-dir DIRNAME] [--include-ns NAMESPACE] [--exclude-ns NAMESPACE] [--interwiki-map FILENAME] [--template-overrides DIRNAME] FILENAME1 [FILENAME2 [...]]",
         EXE_NAME
     );
+    println!();
+    println!("    --include-ns and --exclude-ns can be given multiple times, and take");
+    println!("    either the name or the key of a namespace, as listed in the <siteinfo>");
+    println!("    of the dump. The main namespace can be called \"Main\". By default, only");
+    println!("    the Main and Category namespaces are included.");
+    println!();
+    println!("    --interwiki-map takes a file with a line for each interwiki prefix, like");
+    println!("    \"wikipedia https://en.wikipedia.org/wiki/$1 Wikipedia\", giving the prefix,");
+    println!("    the URL with $1 in place of the title, and optionally the name of the");
+    println!("    site. These are added to the built in prefixes, which include wikipedia,");
+    println!("    wiktionary, commons, meta, mw and Wikia's w.");
+    println!();
+    println!("    --template-overrides takes a directory of files named after templates,");
+    println!("    which are used instead of the templates in the dump. Files ending in");
+    println!("    .wiki have wikitext, which is expanded like the template would be, and");
+    println!("    files ending in .html have HTML, which is put in the page as it is.");
+    println!("    This works for ApiReference, CommandReference, Game and");
+    println!("    UndocumentedFeature too, which are otherwise rendered by this tool, but");
+    println!("    then the pages using them aren't listed on the console commands, games");
+    println!("    and undocumented features pages.");
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{Res, EXE_NAME};
+
+/// Writes the generated files into a staging directory first, and only
+/// moves them into the output directory once everything has been written,
+/// so a run that fails part way through leaves the previous output alone.
+///
+/// A manifest of the generated files is kept in the output directory, so
+/// files from previous runs that are no longer generated can be removed,
+/// without touching anything else that happens to be in the directory.
+pub struct Output {
+    dir: PathBuf,
+    staging_dir: PathBuf,
+    written: Vec<String>,
+}
+
+impl Output {
+    pub fn new(dir: PathBuf) -> Res<Self> {
+        let staging_dir = dir.join(format!(".{}-staging", EXE_NAME));
+
+        // Anything in here is left over from a run that didn't finish.
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+        fs::create_dir(&staging_dir)?;
+
+        Ok(Output {
+            dir,
+            staging_dir,
+            written: Vec::new(),
+        })
+    }
+
+    /// Calls `f` with a writer for the file with the given name, which will
+    /// end up in the output directory when `commit` is called.
+    pub fn write_file(
+        &mut self,
+        file_name: &str,
+        f: impl FnOnce(&mut BufWriter<File>) -> Res<()>
+    ) -> Res<()> {
+        if self.written.iter().any(|w| w == file_name) {
+            return Err(format!("{} was generated twice!", file_name).into());
+        }
+
+        let mut writer = BufWriter::new(
+            File::create(self.staging_dir.join(file_name))?
+        );
+
+        f(&mut writer)?;
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        self.written.push(file_name.to_owned());
+
+        Ok(())
+    }
+
+    /// Moves all the written files into the output directory, replacing
+    /// the previous versions, and removes files that earlier runs generated
+    /// but this one did not.
+    pub fn commit(self, verbose: bool) -> Res<()> {
+        let manifest_path = self.dir.join(format!(".{}-manifest", EXE_NAME));
+
+        let previous = read_manifest(&manifest_path)?;
+
+        // If we get interrupted part way through, the manifest should still
+        // cover everything that might be in the directory.
+        let mut everything: Vec<&str> = previous.iter()
+            .map(|s| s.as_str())
+            .chain(self.written.iter().map(|s| s.as_str()))
+            .collect();
+        everything.sort_unstable();
+        everything.dedup();
+        write_manifest(&manifest_path, &self.staging_dir, &everything)?;
+
+        for file_name in self.written.iter() {
+            fs::rename(
+                self.staging_dir.join(file_name),
+                self.dir.join(file_name)
+            )?;
+        }
+
+        let written: HashSet<&str> = self.written.iter()
+            .map(|s| s.as_str())
+            .collect();
+
+        for file_name in previous.iter() {
+            if written.contains(file_name.as_str()) {
+                continue
+            }
+
+            if verbose {
+                println!("removing stale output file: {}", file_name);
+            }
+
+            match fs::remove_file(self.dir.join(file_name)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        let mut written: Vec<&str> = written.into_iter().collect();
+        written.sort_unstable();
+        write_manifest(&manifest_path, &self.staging_dir, &written)?;
+
+        fs::remove_dir_all(&self.staging_dir)?;
+
+        Ok(())
+    }
+}
+
+fn read_manifest(path: &Path) -> Res<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(
+            s.lines()
+                .filter(|line| !line.is_empty())
+                // Only plain file names are ever written to the manifest, so
+                // anything else means it has been tampered with.
+                .filter(|line| !line.contains(['/', '\\']) && *line != "..")
+                .map(|line| line.to_owned())
+                .collect()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_manifest(path: &Path, staging_dir: &Path, file_names: &[&str]) -> Res<()> {
+    let temp_path = staging_dir.join("manifest");
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    for file_name in file_names {
+        writeln!(writer, "{}", file_name)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    fs::rename(temp_path, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Replacements for templates from the dump, which rely on the site's CSS
+//! or on extensions we don't have, kept in a directory of our own so the
+//! dump doesn't need to be edited.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use super::{normalize_title, Res};
+
+/// The extension of files with wikitext, which is used as the body of the
+/// template, so it can use its arguments and other templates as usual.
+const WIKITEXT_EXTENSION: &str = "wiki";
+
+/// The extension of files with HTML, which is put in the page as it is, in
+/// place of the template.
+const HTML_EXTENSION: &str = "html";
+
+/// What a template is replaced with.
+pub enum Override {
+    Wikitext(String),
+    Html(String),
+}
+
+/// The overrides by the name of the template they replace, without the
+/// namespace prefix.
+#[derive(Default)]
+pub struct Overrides {
+    templates: HashMap<String, Override>,
+}
+
+impl Overrides {
+    /// Loads the overrides from the files in the directory, which are named
+    /// after the template, like `Clr.wiki` or `Navbox.html`. Other files are
+    /// skipped, so the directory can have a README and the like.
+    pub fn load(dir: &Path) -> Res<Self> {
+        let mut overrides = Overrides::default();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            let (stem, extension) = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(extension)) => (stem, extension),
+                _ => continue,
+            };
+            let name = normalize_title(&stem.to_string_lossy());
+
+            let replacement = if extension == WIKITEXT_EXTENSION {
+                Override::Wikitext(fs::read_to_string(&path)?)
+            } else if extension == HTML_EXTENSION {
+                Override::Html(fs::read_to_string(&path)?.trim().to_owned())
+            } else {
+                continue
+            };
+
+            if overrides.templates.insert(name.clone(), replacement).is_some() {
+                return Err(format!(
+                    "{}: there is more than one override for the template {:?}",
+                    dir.display(),
+                    name
+                ).into())
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Returns the override for the template with the given name, without
+    /// the namespace prefix.
+    pub fn get(&self, name: &str) -> Option<&Override> {
+        self.templates.get(&normalize_title(name))
+    }
+}
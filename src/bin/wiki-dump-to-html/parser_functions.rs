@@ -0,0 +1,190 @@
+//! The parser functions from MediaWiki's ParserFunctions extension, which
+//! look like `{{#if: test | then | else}}`.
+
+use super::expr;
+
+/// The arguments of a parser function after the first one, which is the
+/// part between the colon and the first `|`. These only get expanded when
+/// they are needed, like in MediaWiki, so the branch that isn't taken can't
+/// have any effect, and the values are trimmed, as MediaWiki does for all
+/// parser function arguments.
+pub trait Arguments {
+    fn count(&self) -> usize;
+
+    /// Returns the whole argument at the given index, or an empty string if
+    /// there is no such argument.
+    fn value(&self, index: usize) -> String;
+
+    /// Returns the part of the argument before the first `=`, or `None` if
+    /// there isn't one. `#switch` uses this as the case to compare against.
+    fn case(&self, index: usize) -> Option<String>;
+
+    /// Returns the part of the argument after the first `=`.
+    fn case_value(&self, index: usize) -> String;
+}
+
+/// Returns the result of calling the parser function with the given name
+/// and the given, already expanded and trimmed, first argument, or `None`
+/// if we don't know that function.
+pub fn call(
+    name: &str,
+    first: &str,
+    arguments: &impl Arguments,
+    page_exists: &dyn Fn(&str) -> bool,
+) -> Option<String> {
+    // Returns the "then" argument if `is_true`, otherwise the "else" one,
+    // which come after the other arguments of the function.
+    let branch = |other_arguments: usize, is_true: bool| {
+        arguments.value(other_arguments + if is_true { 0 } else { 1 })
+    };
+
+    Some(match name.trim().to_lowercase().as_str() {
+        "if" => branch(0, !first.is_empty()),
+        "ifeq" => branch(1, equals(first, &arguments.value(0))),
+        "ifexist" => branch(0, page_exists(first)),
+        "switch" => switch(first, arguments),
+        "expr" => {
+            if first.is_empty() {
+                return Some(String::new())
+            }
+            match expr::evaluate(first) {
+                Ok(value) => expr::format_number(value),
+                Err(message) => expression_error(&message),
+            }
+        },
+        "ifexpr" => {
+            if first.is_empty() {
+                return Some(branch(0, false))
+            }
+            match expr::evaluate(first) {
+                Ok(value) => branch(0, value != 0.0),
+                Err(message) => expression_error(&message),
+            }
+        },
+        _ => return None,
+    })
+}
+
+/// Returns the value of the first case that matches, where several cases
+/// can share a value by leaving out the `=` on all but the last one, as in
+/// `{{#switch: x | a | b = a or b | #default = neither}}`. A last argument
+/// without an `=` is the default too.
+fn switch(primary: &str, arguments: &impl Arguments) -> String {
+    let mut is_found = false;
+    let mut default = None;
+    let mut last_had_no_equals = false;
+
+    for index in 0..arguments.count() {
+        match arguments.case(index) {
+            Some(case) => {
+                last_had_no_equals = false;
+
+                if is_found || equals(&case, primary) {
+                    return arguments.case_value(index)
+                }
+
+                if case == "#default" {
+                    default = Some(index);
+                }
+            },
+            None => {
+                last_had_no_equals = true;
+
+                if equals(&arguments.value(index), primary) {
+                    is_found = true;
+                }
+            },
+        }
+    }
+
+    match (last_had_no_equals, default) {
+        (true, _) => arguments.value(arguments.count() - 1),
+        (false, Some(index)) => arguments.case_value(index),
+        (false, None) => String::new(),
+    }
+}
+
+/// Compares the values as numbers if they both are numbers, so that for
+/// example "1" and "01.0" are equal, otherwise as strings.
+fn equals(a: &str, b: &str) -> bool {
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Parses the value if it's a plain decimal number, which is what PHP's
+/// `is_numeric` accepts, rather than everything Rust would accept, like
+/// "inf".
+fn number(s: &str) -> Option<f64> {
+    let s = s.trim();
+
+    if !s.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c))
+    || !s.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+        return None
+    }
+
+    s.parse().ok()
+}
+
+fn expression_error(message: &str) -> String {
+    format!("<strong class=\"error\">Expression error: {}</strong>", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Arguments that are already expanded, like `["a", "b = c"]`.
+    struct Plain<'a>(&'a [&'a str]);
+
+    impl Arguments for Plain<'_> {
+        fn count(&self) -> usize {
+            self.0.len()
+        }
+
+        fn value(&self, index: usize) -> String {
+            self.0.get(index).map_or("", |value| value.trim()).to_owned()
+        }
+
+        fn case(&self, index: usize) -> Option<String> {
+            let (case, _) = self.0.get(index)?.split_once('=')?;
+            Some(case.trim().to_owned())
+        }
+
+        fn case_value(&self, index: usize) -> String {
+            let (_, value) = self.0[index].split_once('=').unwrap_or_default();
+            value.trim().to_owned()
+        }
+    }
+
+    #[test]
+    fn switch_picks_the_matching_case() {
+        let arguments = Plain(&["a = 1", "b = 2", "#default = 3"]);
+        assert_eq!(switch("b", &arguments), "2");
+        assert_eq!(switch("z", &arguments), "3");
+    }
+
+    #[test]
+    fn switch_falls_through_cases_without_a_value() {
+        let arguments = Plain(&["a", "b", "c = abc", "d = d"]);
+        assert_eq!(switch("a", &arguments), "abc");
+        assert_eq!(switch("b", &arguments), "abc");
+        assert_eq!(switch("d", &arguments), "d");
+        assert_eq!(switch("z", &arguments), "");
+    }
+
+    #[test]
+    fn switch_takes_a_last_argument_without_equals_as_the_default() {
+        let arguments = Plain(&["a = 1", "#default = 2", "other"]);
+        assert_eq!(switch("a", &arguments), "1");
+        assert_eq!(switch("z", &arguments), "other");
+    }
+
+    #[test]
+    fn switch_compares_numbers_as_numbers() {
+        let arguments = Plain(&["1 = one", "#default = other"]);
+        assert_eq!(switch("01.0", &arguments), "one");
+        assert_eq!(switch("1a", &arguments), "other");
+    }
+}
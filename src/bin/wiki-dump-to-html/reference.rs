@@ -0,0 +1,249 @@
+use parse_wiki_text::{Node, Parameter};
+use std::io::Write;
+
+use super::{
+    plain_text,
+    template_name,
+    write_header,
+    write_nodes,
+    Escaped,
+    Page,
+    Res,
+    Site,
+    Toc,
+    INDEX_FILE_NAME,
+};
+
+/// The name of the template at the top of every Lua API page.
+pub const API_REFERENCE: &str = "ApiReference";
+
+/// The name of the template at the top of every console command page.
+pub const COMMAND_REFERENCE: &str = "CommandReference";
+
+/// The title of the generated page that lists all the console commands.
+pub const CONSOLE_COMMANDS_TITLE: &str = "Console commands";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A Lua function, from `{{ApiReference}}`.
+    Api,
+    /// A command typed at the PICO-8 prompt, from `{{CommandReference}}`.
+    Command,
+}
+
+impl Kind {
+    pub fn from_template_name(name: &str) -> Option<Self> {
+        match name {
+            API_REFERENCE => Some(Kind::Api),
+            COMMAND_REFERENCE => Some(Kind::Command),
+            _ => None,
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Kind::Api => "api",
+            Kind::Command => "command",
+        }
+    }
+}
+
+/// What a `{{ApiReference}}` or `{{CommandReference}}` template says about
+/// a function or a command: its name, a short description, and its
+/// arguments, which are given as three unnamed parameters each: the name,
+/// "optional" if it is optional, and a description.
+pub struct Reference<'a> {
+    kind: Kind,
+    name: &'a [Node<'a>],
+    short_description: &'a [Node<'a>],
+    arguments: Vec<Argument<'a>>,
+}
+
+struct Argument<'a> {
+    name: String,
+    is_optional: bool,
+    description: &'a [Node<'a>],
+}
+
+impl<'a> Reference<'a> {
+    pub fn new(kind: Kind, page_text: &str, parameters: &'a [Parameter<'a>]) -> Self {
+        let mut name: &[Node] = &[];
+        let mut short_description: &[Node] = &[];
+        let mut unnamed = Vec::new();
+
+        for parameter in parameters {
+            match &parameter.name {
+                Some(parameter_name) => {
+                    match plain_text(page_text, parameter_name).as_str() {
+                        "name" => name = &parameter.value,
+                        "shortdesc" => short_description = &parameter.value,
+                        _ => {},
+                    }
+                },
+                None => unnamed.push(&parameter.value[..]),
+            }
+        }
+
+        let arguments = unnamed.chunks(3)
+            .map(|chunk| Argument {
+                name: plain_text(page_text, chunk[0]),
+                is_optional: chunk.get(1)
+                    .is_some_and(|nodes| !plain_text(page_text, nodes).is_empty()),
+                description: chunk.get(2).copied().unwrap_or(&[]),
+            })
+            .filter(|argument| !argument.name.is_empty())
+            .collect();
+
+        Reference {
+            kind,
+            name,
+            short_description,
+            arguments,
+        }
+    }
+
+    /// Returns the reference of the given kind from the top level of the
+    /// page, if there is one.
+    pub fn find(kind: Kind, page_text: &str, nodes: &'a [Node<'a>]) -> Option<Self> {
+        nodes.iter().find_map(|node| match node {
+            Node::Template { name, parameters, .. }
+            if Kind::from_template_name(&template_name(page_text, name)) == Some(kind) => {
+                Some(Reference::new(kind, page_text, parameters))
+            },
+            _ => None,
+        })
+    }
+
+    /// Writes the signature, followed by the short description and a table
+    /// of the arguments.
+    pub fn write(
+        &self,
+        writer: &mut impl Write,
+        site: &Site,
+        toc: &Toc,
+        page_text: &str,
+    ) -> Res<()> {
+        write!(writer, "<div class=\"reference {}\">", self.kind.class())?;
+
+        write!(writer, "<div class=\"signature\">")?;
+        self.write_signature(writer, site, toc, page_text)?;
+        write!(writer, "</div>")?;
+
+        write!(writer, "<p class=\"short-description\">")?;
+        write_nodes(writer, site, toc, page_text, self.short_description)?;
+        write!(writer, "</p>")?;
+
+        if !self.arguments.is_empty() {
+            write!(writer, "<table class=\"arguments\">")?;
+            for argument in self.arguments.iter() {
+                write!(writer, "<tr><th><code>{}</code>", Escaped(&argument.name))?;
+                if argument.is_optional {
+                    write!(writer, " <span class=\"optional\">optional</span>")?;
+                }
+                write!(writer, "</th><td>")?;
+                write_nodes(writer, site, toc, page_text, argument.description)?;
+                write!(writer, "</td></tr>")?;
+            }
+            write!(writer, "</table>")?;
+        }
+
+        write!(writer, "</div>")?;
+
+        Ok(())
+    }
+
+    /// Writes how the function would be called, like `circ( x, y, [r,] [col] )`,
+    /// where a comma goes inside the brackets of an optional argument, since
+    /// it's only needed if the argument is there, or how the command would be
+    /// typed, like `load filename [breadcrumb] [param]`. The name is written
+    /// with its markup, since some pages put more than one form of the
+    /// command in it, on separate lines.
+    fn write_signature(
+        &self,
+        writer: &mut impl Write,
+        site: &Site,
+        toc: &Toc,
+        page_text: &str,
+    ) -> Res<()> {
+        write!(writer, "<code><b>")?;
+        write_nodes(writer, site, toc, page_text, self.name)?;
+        write!(writer, "</b>")?;
+
+        if self.kind == Kind::Api {
+            write!(writer, "(")?;
+        }
+
+        for (i, argument) in self.arguments.iter().enumerate() {
+            let comma = if self.kind == Kind::Api && i + 1 < self.arguments.len() {
+                ","
+            } else {
+                ""
+            };
+
+            if argument.is_optional {
+                write!(writer, " [{}{}]", Escaped(&argument.name), comma)?;
+            } else {
+                write!(writer, " {}{}", Escaped(&argument.name), comma)?;
+            }
+        }
+
+        if self.kind == Kind::Api {
+            write!(writer, " )")?;
+        }
+
+        write!(writer, "</code>")?;
+
+        Ok(())
+    }
+}
+
+/// Writes the overview of the console commands, from the pages that have a
+/// `{{CommandReference}}`, with the synopsis and short description of each.
+pub fn write_console_commands(
+    writer: &mut impl Write,
+    site: &Site,
+    config: &parse_wiki_text::Configuration,
+    command_pages: &[&Page],
+) -> Res<()> {
+    write_header(writer, CONSOLE_COMMANDS_TITLE, "")?;
+
+    write!(writer, "<nav><a href=\"{}\">Index</a></nav>", INDEX_FILE_NAME)?;
+    write!(writer, "<h1>{}</h1>", Escaped(CONSOLE_COMMANDS_TITLE))?;
+
+    write!(
+        writer,
+        "<p>These commands are typed at the PICO-8 prompt. Some of them can \
+        also be called as functions from a cartridge.</p>"
+    )?;
+
+    // The short descriptions can't have headings, so this stays empty.
+    let toc = Toc::new("", &[]);
+
+    write!(writer, "<dl class=\"commands\">")?;
+    for page in command_pages {
+        let parsed = config.parse(&page.text);
+
+        let reference = match Reference::find(Kind::Command, &page.text, &parsed.nodes) {
+            Some(reference) => reference,
+            None => continue,
+        };
+
+        write!(writer, "<dt>")?;
+        match site.file_names.get(&page.title) {
+            Some(file_name) => {
+                write!(writer, "<a href=\"{}\">", Escaped(file_name))?;
+                reference.write_signature(writer, site, &toc, &page.text)?;
+                write!(writer, "</a>")?;
+            },
+            None => reference.write_signature(writer, site, &toc, &page.text)?,
+        }
+        write!(writer, "</dt><dd>")?;
+        write_nodes(writer, site, &toc, &page.text, reference.short_description)?;
+        write!(writer, "</dd>")?;
+    }
+    write!(writer, "</dl>")?;
+
+    write!(writer, "</body></html>")?;
+
+    Ok(())
+}
@@ -0,0 +1,454 @@
+//! HTML from the dump, like the tags written in wikitext and the attributes
+//! on tables, which is only passed through when MediaWiki would allow it,
+//! and is otherwise escaped or left out, since it could contain anything.
+
+use std::io::Write;
+
+use super::{
+    inclusion::{find_tag, starts_with_tag},
+    Escaped,
+    Res,
+};
+
+/// The elements that can be written as HTML tags in wikitext.
+const ELEMENTS: &[&str] = &[
+    "abbr", "b", "bdi", "bdo", "blockquote", "br", "caption", "center", "cite",
+    "code", "data", "dd", "del", "dfn", "div", "dl", "dt", "em", "font",
+    "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "ins", "kbd", "li", "mark",
+    "ol", "p", "pre", "q", "rb", "rp", "rt", "ruby", "s", "samp", "small",
+    "span", "strike", "strong", "sub", "sup", "table", "td", "th", "time",
+    "tr", "tt", "u", "ul", "var", "wbr",
+];
+
+/// The elements that can't have content, so they have no end tag.
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "wbr"];
+
+pub fn is_allowed_element(element: &str) -> bool {
+    ELEMENTS.contains(&element)
+}
+
+pub fn is_void_element(element: &str) -> bool {
+    VOID_ELEMENTS.contains(&element)
+}
+
+/// Returns whether the source, from a `<` to the first `>` after it, is a
+/// whole tag rather than a `<` in prose running on to some later `>`. Like
+/// MediaWiki, the `>` has to come before any other `<`, and we also don't
+/// take a tag over more than one line unless all its attributes are quoted,
+/// since anything else is most likely a paragraph of text.
+pub fn is_well_formed_tag(source: &str) -> bool {
+    let inside = match source.strip_prefix('<').and_then(|tag| tag.strip_suffix('>')) {
+        Some(inside) => inside,
+        None => return false,
+    };
+
+    if inside.contains(['<', '>']) {
+        return false
+    }
+
+    !inside.contains('\n') || has_only_quoted_attributes(tag_attributes(source))
+}
+
+/// Returns whether the attributes are all like `name="value"`, with nothing
+/// else in between.
+fn has_only_quoted_attributes(source: &str) -> bool {
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return true
+        }
+
+        let name_end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == ':' || c == '_'))
+            .unwrap_or(rest.len());
+        if name_end == 0 {
+            return false
+        }
+
+        let after_equals = match rest[name_end..].trim_start().strip_prefix('=') {
+            Some(after_equals) => after_equals.trim_start(),
+            None => return false,
+        };
+
+        let quote = match after_equals.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => return false,
+        };
+        let quoted = &after_equals[1..];
+        rest = match quoted.find(quote) {
+            Some(end) => &quoted[end + 1..],
+            None => return false,
+        };
+    }
+}
+
+/// Returns the text with every `<` that doesn't start a well-formed tag
+/// escaped, the way MediaWiki's sanitizer does it before the text is parsed,
+/// so that something like `if a<b then` stays text, and the paragraphs after
+/// it aren't swallowed into a tag. Comments and the extension tags whose
+/// contents are shown as they are, like `<syntaxhighlight>`, are left alone.
+pub fn escape_stray_tags(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('<') {
+        escaped.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let verbatim_end = if rest.starts_with("<!--") {
+            Some(rest.find("-->").map_or(rest.len(), |end| end + "-->".len()))
+        } else {
+            VERBATIM_EXTENSION_TAGS.iter()
+                .filter(|name| {
+                    rest.get(1..).is_some_and(|tag| starts_with_tag(tag, name))
+                    && rest[1 + name.len()..]
+                        .starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                })
+                .find_map(|name| {
+                    let end_tag = format!("</{}>", name);
+                    find_tag(rest, &end_tag).map(|end| end + end_tag.len())
+                })
+        };
+        if let Some(end) = verbatim_end {
+            escaped.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue
+        }
+
+        let after_slash = rest[1..].strip_prefix('/').unwrap_or(&rest[1..]);
+        let is_stray = after_slash.starts_with(|c: char| c.is_ascii_alphabetic())
+            && rest.find('>').is_some_and(|end| !is_well_formed_tag(&rest[..=end]));
+
+        escaped.push_str(if is_stray { "&lt;" } else { "<" });
+        rest = &rest[1..];
+    }
+
+    escaped.push_str(rest);
+
+    escaped
+}
+
+/// The extension tags that show their contents as they are, so nothing in
+/// them is a tag.
+const VERBATIM_EXTENSION_TAGS: &[&str] = &["syntaxhighlight", "source", "math"];
+
+/// Returns the part of a start tag like `<span class="x">` after the name,
+/// where the attributes are.
+pub fn tag_attributes(tag: &str) -> &str {
+    let tag = tag.strip_prefix('<').unwrap_or(tag);
+    let tag = tag.strip_suffix('>').unwrap_or(tag);
+
+    match tag.find(|c: char| c.is_whitespace() || c == '/') {
+        Some(name_end) => &tag[name_end..],
+        None => "",
+    }
+}
+
+/// The attributes that are allowed on every element.
+const COMMON_ATTRIBUTES: &[&str] = &["class", "id", "style", "title", "lang", "dir"];
+
+/// Returns the attributes that are allowed on the element, other than the
+/// common ones.
+fn element_attributes(element: &str) -> &'static [&'static str] {
+    match element {
+        "table" => &[
+            "border", "cellpadding", "cellspacing", "summary", "width", "align",
+            "bgcolor", "frame", "rules",
+        ],
+        "caption" | "div" | "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => &["align"],
+        "br" => &["clear"],
+        "font" => &["size", "color", "face"],
+        "blockquote" | "q" => &["cite"],
+        "del" | "ins" => &["cite", "datetime"],
+        "time" => &["datetime"],
+        "data" => &["value"],
+        "ol" => &["type", "start", "reversed"],
+        "ul" => &["type"],
+        "li" => &["type", "value"],
+        "tr" => &["align", "valign", "bgcolor"],
+        "td" | "th" => &[
+            "abbr", "axis", "headers", "scope", "rowspan", "colspan", "nowrap",
+            "width", "height", "bgcolor", "align", "valign",
+        ],
+        _ => &[],
+    }
+}
+
+/// The CSS properties that are kept in `style` attributes. They can only
+/// change how things look, not fetch anything or cover the rest of the page.
+const STYLE_PROPERTIES: &[&str] = &[
+    "background", "background-color",
+    "border", "border-bottom", "border-collapse", "border-color", "border-left",
+    "border-radius", "border-right", "border-spacing", "border-style", "border-top",
+    "border-width",
+    "clear", "color", "display", "float",
+    "font", "font-family", "font-size", "font-style", "font-variant", "font-weight",
+    "height", "line-height", "list-style", "list-style-type",
+    "margin", "margin-bottom", "margin-left", "margin-right", "margin-top",
+    "max-width", "min-width",
+    "padding", "padding-bottom", "padding-left", "padding-right", "padding-top",
+    "text-align", "text-decoration", "text-indent", "text-transform",
+    "vertical-align", "white-space", "width",
+];
+
+/// Things that make a CSS value unsafe, wherever they appear in it, since
+/// they can load things from elsewhere or run scripts in old browsers.
+const UNSAFE_STYLE_VALUES: &[&str] = &[
+    "url(", "image(", "image-set(", "attr(", "expression", "javascript:",
+    "behavior", "-moz-binding", "\\", "/*", "<", ">",
+];
+
+/// Writes the attributes from the source that are allowed on the element,
+/// each with a leading space, so this can go right after the element name.
+pub fn write_attributes(writer: &mut impl Write, element: &str, source: &str) -> Res<()> {
+    write!(writer, "{}", sanitize_attributes(element, source))?;
+
+    Ok(())
+}
+
+/// Returns the attributes from the source that are allowed on the element,
+/// the way `write_attributes` writes them.
+pub fn sanitize_attributes(element: &str, source: &str) -> String {
+    let mut allowed: Vec<(String, String)> = Vec::new();
+
+    for (name, value) in parse_attributes(source) {
+        if !COMMON_ATTRIBUTES.contains(&name.as_str())
+        && !element_attributes(element).contains(&name.as_str()) {
+            continue
+        }
+
+        let value = match name.as_str() {
+            "style" => match sanitize_style(&value) {
+                style if style.is_empty() => continue,
+                style => style,
+            },
+            "rowspan" | "colspan" => match value.trim().parse::<u16>() {
+                Ok(span) if span > 0 => span.to_string(),
+                _ => continue,
+            },
+            _ => value,
+        };
+
+        // Like in HTML, the last one wins, but it stays where the first was.
+        match allowed.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, existing)) => *existing = value,
+            None => allowed.push((name, value)),
+        }
+    }
+
+    allowed.iter()
+        .map(|(name, value)| format!(" {}=\"{}\"", name, Escaped(value)))
+        .collect()
+}
+
+/// Splits the source into attribute names, lowercased, and their values,
+/// with character references decoded. Attributes without a value, like
+/// `nowrap`, get an empty one.
+fn parse_attributes(source: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/' || c == '>')
+            .unwrap_or(rest.len());
+        if name_end == 0 {
+            // Something like a stray `=` or `>`, which can't start a name.
+            rest = &rest[1..];
+            continue
+        }
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = "";
+        if let Some(after_equals) = rest.strip_prefix('=') {
+            let after_equals = after_equals.trim_start();
+
+            match after_equals.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let quoted = &after_equals[1..];
+                    let end = quoted.find(quote).unwrap_or(quoted.len());
+                    value = &quoted[..end];
+                    rest = quoted.get(end + 1..).unwrap_or("");
+                },
+                _ => {
+                    let end = after_equals.find(char::is_whitespace)
+                        .unwrap_or(after_equals.len());
+                    value = &after_equals[..end];
+                    rest = &after_equals[end..];
+                },
+            }
+        }
+
+        attributes.push((name, decode_character_references(value)));
+    }
+
+    attributes
+}
+
+/// Decodes the character references that show up in attribute values and
+/// text, which is the numeric ones and the few that HTML needs.
+pub fn decode_character_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            let character = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => name.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                },
+            };
+            character.map(|character| (character, end))
+        });
+
+        match reference {
+            Some((character, end)) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// Returns the declarations of the style that are for allowed properties
+/// and have safe values, or an empty string if there are none.
+fn sanitize_style(style: &str) -> String {
+    let mut sanitized = String::new();
+
+    for declaration in style.split(';') {
+        let (property, value) = match declaration.split_once(':') {
+            Some((property, value)) => (property.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        let lowercase_value = value.to_ascii_lowercase();
+        if !STYLE_PROPERTIES.contains(&property.as_str())
+        || value.is_empty()
+        || UNSAFE_STYLE_VALUES.iter().any(|unsafe_value| lowercase_value.contains(unsafe_value)) {
+            continue
+        }
+
+        sanitized.push_str(&format!("{}:{};", property, value));
+    }
+
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_attributes_that_are_not_allowed() {
+        assert_eq!(
+            sanitize_attributes("span", r#"class="x" onclick="alert(1)" href="y""#),
+            r#" class="x""#
+        );
+        assert_eq!(sanitize_attributes("span", "colspan=2"), "");
+        assert_eq!(sanitize_attributes("td", "colspan=2 nowrap"), r#" colspan="2" nowrap="""#);
+    }
+
+    #[test]
+    fn drops_spans_that_are_not_numbers() {
+        assert_eq!(sanitize_attributes("td", r#"colspan="x" rowspan="0""#), "");
+        assert_eq!(sanitize_attributes("td", r#"colspan=" 3 ""#), r#" colspan="3""#);
+    }
+
+    #[test]
+    fn decodes_character_references_and_escapes_again() {
+        assert_eq!(
+            sanitize_attributes("span", r#"title="a &amp; b &lt;c&gt; &#34;d&#x22; &bogus;""#),
+            r#" title="a &amp; b &lt;c&gt; &quot;d&quot; &amp;bogus;""#
+        );
+        assert_eq!(
+            sanitize_attributes("span", r#"title='it"s'"#),
+            r#" title="it&quot;s""#
+        );
+    }
+
+    #[test]
+    fn keeps_the_last_of_duplicate_attributes_where_the_first_was() {
+        assert_eq!(
+            sanitize_attributes("span", r#"class="a" id="b" CLASS="c""#),
+            r#" class="c" id="b""#
+        );
+    }
+
+    #[test]
+    fn keeps_safe_style_declarations() {
+        assert_eq!(
+            sanitize_style("Color: red; position: fixed; width:10px"),
+            "color:red;width:10px;"
+        );
+    }
+
+    #[test]
+    fn rejects_unsafe_style_values() {
+        for style in [
+            "background: url(http://example.com/x.png)",
+            "width: expression(alert(1))",
+            "color: r\\65 d",
+            "color: red /* comment */",
+            "background: URL(x)",
+        ] {
+            assert_eq!(sanitize_style(style), "", "{}", style);
+        }
+
+        assert_eq!(sanitize_attributes("span", r#"style="width: expression(1)""#), "");
+    }
+
+    #[test]
+    fn only_takes_well_formed_tags() {
+        assert!(is_well_formed_tag("<b>"));
+        assert!(is_well_formed_tag(r#"<span class="x">"#));
+        assert!(is_well_formed_tag("<span\n  class=\"x\"\n  style='color:red'>"));
+        assert!(!is_well_formed_tag("<b then c=1 end\n\nNext para with a ->"));
+        assert!(!is_well_formed_tag(r#"<b y <strong class="error">"#));
+        assert!(!is_well_formed_tag("<b"));
+    }
+
+    #[test]
+    fn escapes_stray_tags_before_parsing() {
+        assert_eq!(
+            escape_stray_tags("if a<b then c=1 end\n\nNext para with a -> arrow."),
+            "if a&lt;b then c=1 end\n\nNext para with a -> arrow."
+        );
+        assert_eq!(
+            escape_stray_tags(r#"a<b y <strong class="error">x</strong>"#),
+            r#"a&lt;b y <strong class="error">x</strong>"#
+        );
+        assert_eq!(escape_stray_tags("1 < 2 and <br/>"), "1 < 2 and <br/>");
+        assert_eq!(
+            escape_stray_tags("<!-- a<b -->\n<math>a<b</math>"),
+            "<!-- a<b -->\n<math>a<b</math>"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use super::Res;
+
+/// What we know about the wiki as a whole, from the `<siteinfo>` element
+/// at the start of the dump.
+#[derive(Debug, Default)]
+pub struct SiteInfo {
+    pub site_name: String,
+    pub namespaces: Namespaces,
+}
+
+impl SiteInfo {
+    /// Parses the `<siteinfo>` element out of the text of the whole dump.
+    /// The `parse_mediawiki_dump` crate skips this element, so we do it
+    /// ourselves, with just enough XML handling for this one element.
+    pub fn parse(dump: &str) -> Res<Self> {
+        let site_info = match element_contents(dump, "siteinfo") {
+            Some(site_info) => site_info,
+            None => return Err("Could not find the <siteinfo> in the dump!".into()),
+        };
+
+        let site_name = element_contents(site_info, "sitename")
+            .map(unescape_xml)
+            .unwrap_or_default();
+
+        let mut namespaces = Namespaces::default();
+
+        let mut rest = element_contents(site_info, "namespaces").unwrap_or("");
+        while let Some(start) = rest.find("<namespace ") {
+            rest = &rest[start..];
+
+            let tag_end = rest.find('>')
+                .ok_or("Unterminated <namespace> in the <siteinfo>!")?;
+            let tag = &rest[..tag_end];
+
+            let key = attribute(tag, "key")
+                .and_then(|key| key.parse::<i32>().ok())
+                .ok_or("Missing or invalid namespace key in the <siteinfo>!")?;
+
+            let name = if tag.ends_with('/') {
+                rest = &rest[tag_end..];
+                String::new()
+            } else {
+                let name_end = rest.find("</namespace>")
+                    .ok_or("Unterminated <namespace> in the <siteinfo>!")?;
+                let name = unescape_xml(&rest[tag_end + 1..name_end]);
+                rest = &rest[name_end..];
+                name
+            };
+
+            namespaces.names.insert(key, name);
+        }
+
+        Ok(SiteInfo {
+            site_name,
+            namespaces,
+        })
+    }
+
+    /// Adds in the information from another dump, with this one taking
+    /// precedence where they differ.
+    pub fn merge(&mut self, other: SiteInfo) {
+        if self.site_name.is_empty() {
+            self.site_name = other.site_name;
+        }
+
+        for (key, name) in other.namespaces.names {
+            self.namespaces.names.entry(key).or_insert(name);
+        }
+    }
+}
+
+/// The namespaces defined by the wiki, by key.
+#[derive(Debug, Default)]
+pub struct Namespaces {
+    names: BTreeMap<i32, String>,
+}
+
+impl Namespaces {
+    /// Returns the key of the namespace referred to by `spec`, which can be
+    /// either the key itself or the name of the namespace. The main
+    /// namespace, which has no name, can be referred to as "Main", and the
+    /// project namespace, which is named after the site, as "Project".
+    pub fn key(&self, spec: &str) -> Option<i32> {
+        let spec = spec.trim();
+
+        if let Ok(key) = spec.parse::<i32>() {
+            return if self.names.contains_key(&key) { Some(key) } else { None };
+        }
+
+        let spec = spec.replace('_', " ");
+        if spec.eq_ignore_ascii_case("Main") || spec.eq_ignore_ascii_case("(Main)") {
+            return Some(0);
+        }
+        if spec.eq_ignore_ascii_case("Project") {
+            return Some(4);
+        }
+
+        self.names.iter()
+            .find(|(_, name)| !name.is_empty() && name.eq_ignore_ascii_case(&spec))
+            .map(|(key, _)| *key)
+    }
+
+    /// Returns the name of the namespace with the given key, with the main
+    /// namespace being called "Main".
+    pub fn name(&self, key: i32) -> Option<&str> {
+        match self.names.get(&key) {
+            Some(name) if name.is_empty() => Some("Main"),
+            Some(name) => Some(name),
+            None => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &str)> {
+        self.names.keys().filter_map(move |&key| Some((key, self.name(key)?)))
+    }
+}
+
+/// Returns the text between the first `<name>` and the following `</name>`.
+fn element_contents<'text>(text: &'text str, name: &str) -> Option<&'text str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+
+    let start = text.find(&open)? + open.len();
+    let end = start + text[start..].find(&close)?;
+
+    Some(&text[start..end])
+}
+
+fn attribute<'tag>(tag: &'tag str, name: &str) -> Option<&'tag str> {
+    let prefix = format!(" {}=\"", name);
+
+    let start = tag.find(&prefix)? + prefix.len();
+    let end = start + tag[start..].find('"')?;
+
+    Some(&tag[start..end])
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,211 @@
+//! Strip markers, which stand in for HTML that is ready to go in the page,
+//! while the rest of the page is expanded and parsed, so it doesn't get
+//! touched by either. MediaWiki uses these for `<nowiki>`, `<pre>` and
+//! extension tags like `<syntaxhighlight>`, which show their contents as they
+//! are, and we also use them for the HTML of template overrides.
+
+use std::{cell::RefCell, io::Write};
+
+use super::{
+    inclusion::{find_tag, starts_with_tag},
+    sanitizer::{self, decode_character_references as decode},
+    Escaped,
+    Res,
+};
+
+// These start and end with a character that can't be in wikitext, so they
+// pass through the parser as plain text, and can't be typed by accident.
+const MARKER_PREFIX: &str = "\u{7f}UNIQ-";
+const MARKER_SUFFIX: &str = "-QINU\u{7f}";
+
+/// The HTML that the markers stand in for, for every page, so the markers
+/// can be replaced when the pages are written.
+#[derive(Default)]
+pub struct StripMarkers {
+    html: RefCell<Vec<String>>,
+}
+
+impl StripMarkers {
+    /// Returns the marker that stands in for the HTML.
+    pub fn insert(&self, html: String) -> String {
+        let mut all_html = self.html.borrow_mut();
+        all_html.push(html);
+
+        format!("{}{}{}", MARKER_PREFIX, all_html.len() - 1, MARKER_SUFFIX)
+    }
+
+    /// Returns the text with every `<nowiki>`, `<pre>`, `<syntaxhighlight>`
+    /// and `<source>` section replaced with a marker for its contents,
+    /// escaped. Comments are left alone, so the tags can be commented out.
+    pub fn strip_tags(&self, text: &str) -> String {
+        let mut stripped = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(i) = rest.find('<') {
+            stripped.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            if rest.starts_with("<!--") {
+                let end = rest.find("-->").map_or(rest.len(), |end| end + "-->".len());
+                stripped.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue
+            }
+
+            let section = ["nowiki", "pre", "syntaxhighlight", "source"].iter()
+                .find_map(|&name| tag_section(rest, name).map(|section| (name, section)));
+
+            match section {
+                Some((name, section)) => {
+                    let html = match name {
+                        "nowiki" => escape_tags_only(section.content),
+                        "pre" => {
+                            // MediaWiki lets `<nowiki>` be used inside
+                            // `<pre>` too, though it does nothing there.
+                            let content = remove_tags(
+                                &remove_tags(section.content, "<nowiki>"),
+                                "</nowiki>"
+                            );
+
+                            format!(
+                                "<pre{}>{}</pre>",
+                                sanitizer::sanitize_attributes("pre", section.attributes),
+                                escape_tags_only(&content)
+                            )
+                        },
+                        // Code is shown exactly as it is written, even the
+                        // tags and character references in it.
+                        _ => format!("<pre>{}</pre>", Escaped(section.content)),
+                    };
+
+                    stripped.push_str(&self.insert(html));
+                    rest = &rest[section.length..];
+                },
+                None => {
+                    stripped.push('<');
+                    rest = &rest[1..];
+                },
+            }
+        }
+
+        stripped.push_str(rest);
+
+        stripped
+    }
+
+    /// Writes the text escaped, except for the markers, which are replaced
+    /// with their HTML. Character references like `&#8239;`, which the
+    /// parser leaves in the text, are decoded first, so they aren't shown as
+    /// they are written.
+    pub fn write_text(&self, writer: &mut impl Write, text: &str) -> Res<()> {
+        let all_html = self.html.borrow();
+        let mut rest = text;
+
+        while let Some(start) = rest.find(MARKER_PREFIX) {
+            let after_prefix = &rest[start + MARKER_PREFIX.len()..];
+
+            let html = after_prefix.find(MARKER_SUFFIX).and_then(|end| {
+                let html = all_html.get(after_prefix[..end].parse::<usize>().ok()?)?;
+                Some((html, &after_prefix[end + MARKER_SUFFIX.len()..]))
+            });
+
+            match html {
+                Some((html, after)) => {
+                    write!(writer, "{}{}", Escaped(&decode(&rest[..start])), html)?;
+                    rest = after;
+                },
+                None => {
+                    write!(writer, "{}", Escaped(&decode(&rest[..start + MARKER_PREFIX.len()])))?;
+                    rest = after_prefix;
+                },
+            }
+        }
+
+        write!(writer, "{}", Escaped(&decode(rest)))?;
+
+        Ok(())
+    }
+}
+
+/// Returns the text without the markers, for where HTML can't go, like
+/// heading anchors.
+pub fn remove_markers(text: &str) -> String {
+    let mut removed = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while let Some(start) = rest.find(MARKER_PREFIX) {
+        removed.push_str(&rest[..start]);
+
+        let after_prefix = &rest[start + MARKER_PREFIX.len()..];
+        rest = match after_prefix.find(MARKER_SUFFIX) {
+            Some(end) => &after_prefix[end + MARKER_SUFFIX.len()..],
+            None => after_prefix,
+        };
+    }
+    removed.push_str(rest);
+
+    removed
+}
+
+/// A section like `<pre class="x">content</pre>` at the start of some text.
+struct TagSection<'text> {
+    attributes: &'text str,
+    content: &'text str,
+    /// The length of the whole section, including the tags.
+    length: usize,
+}
+
+/// Returns the section of the element with the given name that the text
+/// starts with, if it does. A tag like `<nowiki/>` makes an empty section,
+/// and a start tag that is never closed doesn't make one at all, so it is
+/// shown as it is, like MediaWiki does.
+fn tag_section<'text>(text: &'text str, name: &str) -> Option<TagSection<'text>> {
+    let after_name = text.get(1..)
+        .filter(|rest| starts_with_tag(rest, name))
+        .map(|rest| &rest[name.len()..])?;
+    if !after_name.starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>') {
+        return None
+    }
+
+    let start_tag_end = after_name.find('>')?;
+    let start_tag_length = text.len() - after_name.len() + start_tag_end + 1;
+
+    if after_name[..start_tag_end].ends_with('/') {
+        return Some(TagSection {
+            attributes: &after_name[..start_tag_end - 1],
+            content: "",
+            length: start_tag_length,
+        })
+    }
+
+    let end_tag = format!("</{}>", name);
+    let after_start_tag = &text[start_tag_length..];
+    let content_length = find_tag(after_start_tag, &end_tag)?;
+
+    Some(TagSection {
+        attributes: &after_name[..start_tag_end],
+        content: &after_start_tag[..content_length],
+        length: start_tag_length + content_length + end_tag.len(),
+    })
+}
+
+/// Returns the text with the given tags removed, ignoring case.
+fn remove_tags(text: &str, tag: &str) -> String {
+    let mut removed = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while let Some(i) = find_tag(rest, tag) {
+        removed.push_str(&rest[..i]);
+        rest = &rest[i + tag.len()..];
+    }
+    removed.push_str(rest);
+
+    removed
+}
+
+/// Escapes the text like MediaWiki does for `<nowiki>` and `<pre>`, where
+/// tags are shown as they are, but character references like `&amp;` still
+/// work.
+fn escape_tags_only(text: &str) -> String {
+    text.replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
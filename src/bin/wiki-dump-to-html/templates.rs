@@ -0,0 +1,741 @@
+use parse_wiki_text::{Configuration, Node, Positioned};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+};
+
+use super::{
+    child_lists,
+    games,
+    inclusion::{self, Use},
+    interwiki::Interwiki,
+    magic_words,
+    nodes_source,
+    normalize_title,
+    overrides::{Override, Overrides},
+    parser_functions,
+    reference,
+    site_info::SiteInfo,
+    source_end,
+    strip::StripMarkers,
+    undocumented,
+    Page,
+};
+
+/// The key of the namespace that templates live in, on every MediaWiki site.
+pub const TEMPLATE_NAMESPACE: u32 = 10;
+
+/// The templates that we render ourselves, instead of expanding them, so
+/// they are left in the page text for the renderer to find.
+const NATIVE_TEMPLATES: &[&str] = &[
+    reference::API_REFERENCE,
+    reference::COMMAND_REFERENCE,
+    games::GAME,
+    undocumented::UNDOCUMENTED_FEATURE,
+];
+
+/// Templates that are used as if every wiki had them, for when the dump
+/// doesn't, by name and body.
+const BUILT_IN_TEMPLATES: &[(&str, &str)] = &[
+    // Links to Wikipedia, like `{{w|Lua (programming language)|Lua}}`.
+    ("W", "[[wikipedia:{{{1}}}|{{{2|{{{1}}}}}}]]"),
+];
+
+/// MediaWiki gives up at this depth, so well-behaved templates stay below it.
+const MAX_DEPTH: usize = 40;
+
+/// MediaWiki stops expanding templates on a page once what they have
+/// expanded to adds up to this many bytes, which keeps templates that call
+/// themselves more than once from taking forever.
+const MAX_EXPANDED_SIZE: usize = 2 * 1024 * 1024;
+
+const SIZE_LIMIT_EXCEEDED: &str = "Template expansion size limit exceeded";
+
+/// How many templates a page can call, counting the ones called by other
+/// templates, like MediaWiki's limit on preprocessor nodes. Templates that
+/// call themselves more than once, but expand to nothing, never reach the
+/// size limit, and would otherwise take forever.
+const MAX_TEMPLATE_CALLS: usize = 20_000;
+
+const CALL_LIMIT_EXCEEDED: &str = "Template call limit exceeded";
+
+/// The state of expanding a single page, shared by all the frames.
+struct PageState<'page> {
+    /// The title of the page being expanded, so `{{PAGENAME}}` in a
+    /// template refers to the page that uses it.
+    title: &'page str,
+    /// The total size of everything the templates have expanded to so far.
+    expanded_size: Cell<usize>,
+    /// How many templates have been called so far.
+    template_calls: Cell<usize>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+impl PageState<'_> {
+    fn report(&self, diagnostic: Diagnostic) {
+        let mut diagnostics = self.diagnostics.borrow_mut();
+
+        // A template that is cut off is usually cut off many times over.
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+/// The arguments a template was called with, by name, where the unnamed
+/// ones are named by their position, starting from 1.
+struct Frame<'page> {
+    page: &'page PageState<'page>,
+    /// The titles of the templates that led to this frame, starting with
+    /// the one the page itself used, and ending with this frame's template.
+    chain: Vec<String>,
+    arguments: HashMap<String, String>,
+}
+
+/// Something that went wrong while expanding a page, which cut off the
+/// expansion of a template.
+#[derive(PartialEq, Eq)]
+pub struct Diagnostic {
+    message: &'static str,
+    /// The titles of the templates that led to the one that was cut off,
+    /// followed by that one.
+    chain: Vec<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, in {}", self.message, self.chain.join(" -> "))
+    }
+}
+
+/// Expands `{{template}}` invocations into wikitext, using the pages from
+/// the dump as the template bodies, the way MediaWiki does before parsing
+/// the page for real.
+pub struct Expander<'a> {
+    config: &'a Configuration,
+    site_info: &'a SiteInfo,
+    interwiki: &'a Interwiki,
+    overrides: &'a Overrides,
+    /// For what is shown as it is, like the contents of `<nowiki>`.
+    markers: &'a StripMarkers,
+    /// The text of every page in the dump, by title, since any page can be
+    /// transcluded, not just the ones in the template namespace.
+    pages: HashMap<String, String>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new<'page>(
+        config: &'a Configuration,
+        site_info: &'a SiteInfo,
+        interwiki: &'a Interwiki,
+        overrides: &'a Overrides,
+        markers: &'a StripMarkers,
+        pages: impl IntoIterator<Item = &'page Page>,
+    ) -> Self {
+        Expander {
+            config,
+            site_info,
+            interwiki,
+            overrides,
+            markers,
+            pages: pages.into_iter()
+                .map(|page| (page.title.clone(), page.text.clone()))
+                .collect(),
+        }
+    }
+
+    /// Returns the text of the page with the given title, with all the
+    /// templates expanded, along with anything that went wrong.
+    pub fn expand_page(&self, title: &str, text: &str) -> (String, Vec<Diagnostic>) {
+        let page = PageState {
+            title,
+            expanded_size: Cell::new(0),
+            template_calls: Cell::new(0),
+            diagnostics: RefCell::new(Vec::new()),
+        };
+
+        let frame = Frame {
+            page: &page,
+            chain: Vec::new(),
+            arguments: HashMap::new(),
+        };
+
+        // What is inside `<nowiki>`, `<pre>` and code doesn't get expanded.
+        let text = self.markers.strip_tags(&inclusion::apply(text, Use::Viewed));
+
+        let expanded = self.expand(&text, &frame);
+
+        (expanded, page.diagnostics.into_inner())
+    }
+
+    fn expand(&self, text: &str, frame: &Frame) -> String {
+        let parsed = self.config.parse(text);
+
+        let mut replacements = Vec::new();
+        self.expand_nodes(text, &parsed.nodes, frame, &mut replacements);
+
+        // The parser moves things it doesn't expect inside tables to before
+        // the table, so the nodes aren't always in the order of the text.
+        replacements.sort_by_key(|&(start, _, _)| start);
+
+        let mut output = String::with_capacity(text.len());
+        let mut position = 0;
+
+        for (start, end, replacement) in replacements {
+            if start < position {
+                continue
+            }
+
+            output.push_str(&text[position..start]);
+            output.push_str(&replacement);
+            position = end;
+        }
+
+        output.push_str(&text[position..]);
+
+        output
+    }
+
+    /// Collects what to replace the templates in the nodes with, as the
+    /// start and end of the text to replace, along with the replacement.
+    fn expand_nodes(
+        &self,
+        text: &str,
+        nodes: &[Node],
+        frame: &Frame,
+        replacements: &mut Vec<(usize, usize, String)>,
+    ) {
+        for node in nodes {
+            match node {
+                Node::Template { name, parameters, .. } => {
+                    if let Some(expanded) = self.expand_template(
+                        text,
+                        name,
+                        parameters,
+                        frame
+                    ) {
+                        replacements.push((node.start(), node.end(), expanded));
+                    } else {
+                        for children in child_lists(node) {
+                            self.expand_nodes(text, children, frame, replacements);
+                        }
+                    }
+                },
+                Node::Parameter { name, default, .. } => {
+                    if let Some(expanded) = self.expand_parameter(
+                        text,
+                        name,
+                        default.as_deref(),
+                        frame
+                    ) {
+                        replacements.push((node.start(), source_end(node), expanded));
+                    }
+                },
+                // The parser doesn't look inside tags, but MediaWiki expands
+                // templates in attributes too, so we expand the tag without
+                // its `<`, so it doesn't get parsed as a tag again.
+                Node::StartTag { .. } | Node::EndTag { .. } => {
+                    let source = &text[node.start()..node.end()];
+                    if source.contains("{{") {
+                        let expanded = format!("<{}", self.expand(&source[1..], frame));
+                        replacements.push((node.start(), node.end(), expanded));
+                    }
+                },
+                _ => {
+                    for children in child_lists(node) {
+                        self.expand_nodes(text, children, frame, replacements);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the expansion of the template, or `None` if the invocation
+    /// should be left as it is.
+    fn expand_template(
+        &self,
+        text: &str,
+        name: &[Node],
+        parameters: &[parse_wiki_text::Parameter],
+        frame: &Frame,
+    ) -> Option<String> {
+        let name = self.expand(nodes_source(text, name)?, frame);
+        let name = name.trim();
+
+        // Parser functions, which look like `{{#if:...}}`.
+        if let Some(function) = name.strip_prefix('#') {
+            let (function, first) = function.split_once(':')?;
+
+            let arguments = LazyArguments {
+                expander: self,
+                text,
+                parameters,
+                frame,
+            };
+
+            return parser_functions::call(
+                function,
+                first.trim(),
+                &arguments,
+                &|title| self.page_exists(title)
+            )
+        }
+
+        if let Some(value) = self.magic_word(name, frame) {
+            return Some(value)
+        }
+
+        // Transcluding from other wikis doesn't work offline, so we link to
+        // the page instead.
+        if self.interwiki.is_interwiki(name) {
+            return Some(format!("[[:{}]]", name.trim_start_matches(':')))
+        }
+
+        let title = self.template_title(name);
+
+        // Our own replacements win over the dump, and over our own rendering.
+        let template_override = self.template_override(&title);
+
+        if template_override.is_none()
+        && NATIVE_TEMPLATES.iter().any(|&native| self.template_title(native) == title) {
+            return None
+        }
+
+        let mut chain = frame.chain.clone();
+        chain.push(title.clone());
+
+        // Like MediaWiki, we show the error where the template would be.
+        let placeholder = |message: &str| Some(format!(
+            "<strong class=\"error\">{}: [[:{}]]</strong>",
+            message,
+            title
+        ));
+        let error = |message: &'static str| {
+            frame.page.report(Diagnostic {
+                message,
+                chain: chain.clone(),
+            });
+            placeholder(message)
+        };
+
+        if frame.chain.contains(&title) {
+            return error("Template loop detected")
+        }
+        if frame.chain.len() >= MAX_DEPTH {
+            return error("Template recursion depth exceeded")
+        }
+
+        // Once the page is over its budget, every template left on it gets
+        // cut off, but only the one that went over is worth reporting.
+        if frame.page.expanded_size.get() > MAX_EXPANDED_SIZE {
+            return placeholder(SIZE_LIMIT_EXCEEDED)
+        }
+
+        let template_calls = frame.page.template_calls.get() + 1;
+        frame.page.template_calls.set(template_calls);
+        if template_calls == MAX_TEMPLATE_CALLS + 1 {
+            return error(CALL_LIMIT_EXCEEDED)
+        }
+        if template_calls > MAX_TEMPLATE_CALLS {
+            return placeholder(CALL_LIMIT_EXCEEDED)
+        }
+
+        let built_in = || BUILT_IN_TEMPLATES.iter()
+            .find(|(name, _)| self.template_title(name) == title)
+            .map(|(_, body)| *body);
+
+        let body = match template_override {
+            Some(Override::Html(html)) => return Some(self.markers.insert(html.clone())),
+            Some(Override::Wikitext(body)) => Some(body.as_str()),
+            None => self.template_body(&title).or_else(built_in),
+        };
+
+        let body = match body {
+            Some(body) => self.markers.strip_tags(&inclusion::apply(body, Use::Transcluded)),
+            // MediaWiki shows a link to the missing template.
+            None => return Some(format!("[[:{}]]", title)),
+        };
+
+        let mut callee_frame = Frame {
+            page: frame.page,
+            chain: chain.clone(),
+            arguments: HashMap::new(),
+        };
+        let mut position = 1;
+        for parameter in parameters {
+            match &parameter.name {
+                Some(name) => {
+                    let name = nodes_source(text, name)
+                        .map(|name| self.expand(name, frame))
+                        .unwrap_or_default();
+                    let value = self.expand(
+                        parameter_value_source(text, parameter),
+                        frame
+                    );
+
+                    // Named arguments have the whitespace around them
+                    // trimmed, and later ones win over earlier ones.
+                    callee_frame.arguments.insert(
+                        name.trim().to_owned(),
+                        value.trim().to_owned()
+                    );
+                },
+                None => {
+                    let value = self.expand(
+                        parameter_source(text, parameter),
+                        frame
+                    );
+
+                    callee_frame.arguments.insert(position.to_string(), value);
+                    position += 1;
+                },
+            }
+        }
+
+        let mut expanded = self.expand(&body, &callee_frame);
+
+        // A template inside this one may have gone over already.
+        if frame.page.expanded_size.get() > MAX_EXPANDED_SIZE {
+            return placeholder(SIZE_LIMIT_EXCEEDED)
+        }
+        let expanded_size = frame.page.expanded_size.get() + expanded.len();
+        frame.page.expanded_size.set(expanded_size);
+        if expanded_size > MAX_EXPANDED_SIZE {
+            return error(SIZE_LIMIT_EXCEEDED)
+        }
+
+        // MediaWiki puts these at the start of a line, so they work as block
+        // level markup even when the template is used in the middle of one.
+        if ["{|", ":", ";", "#", "*"].iter().any(|s| expanded.starts_with(s)) {
+            expanded.insert(0, '\n');
+        }
+
+        Some(expanded)
+    }
+
+    /// Returns the value for a `{{{name|default}}}` parameter reference:
+    /// the argument with that name if the template was called with one,
+    /// even if it is empty, otherwise the default if there is one. If there
+    /// is neither, the reference is left as it is, like MediaWiki does.
+    fn expand_parameter(
+        &self,
+        text: &str,
+        name: &[Node],
+        default: Option<&[Node]>,
+        frame: &Frame,
+    ) -> Option<String> {
+        let name = nodes_source(text, name)
+            .map(|name| self.expand(name, frame))
+            .unwrap_or_default();
+
+        if let Some(value) = frame.arguments.get(name.trim()) {
+            return Some(value.clone());
+        }
+
+        // Defaults only get expanded when they are actually used.
+        default.map(|default| {
+            nodes_source(text, default)
+                .map(|default| self.expand(default, frame))
+                .unwrap_or_default()
+        })
+    }
+
+    /// Returns the value of the variable, if `{{name}}` is one. Variables
+    /// about a page can also be given another title than the current page's,
+    /// as in `{{PAGENAME:Some title}}`.
+    fn magic_word(&self, name: &str, frame: &Frame) -> Option<String> {
+        let (word, title) = match name.split_once(':') {
+            Some((word, title)) => (word.trim(), self.full_title(title.trim())),
+            None => {
+                if let Some(value) = magic_words::site_variable(name, self.site_info) {
+                    return Some(value)
+                }
+
+                (name, frame.page.title.to_owned())
+            },
+        };
+
+        let namespaces = &self.site_info.namespaces;
+        let title = match self.split_namespace(&title) {
+            Some((namespace, text)) => magic_words::Title {
+                namespace: namespaces.key(namespace)?,
+                text,
+            },
+            None => magic_words::Title {
+                namespace: 0,
+                text: &title,
+            },
+        };
+
+        magic_words::title_variable(word, &title, namespaces)
+    }
+
+    /// Returns the title of the page that `{{name}}` refers to. That's a
+    /// page in the template namespace, unless the name is prefixed with
+    /// another namespace, or with a colon for the main namespace.
+    fn template_title(&self, name: &str) -> String {
+        if let Some(rest) = name.strip_prefix(':') {
+            return self.full_title(rest);
+        }
+
+        if self.split_namespace(name).is_some() {
+            return self.full_title(name);
+        }
+
+        let template_namespace = self.site_info.namespaces
+            .name(TEMPLATE_NAMESPACE as i32)
+            .unwrap_or("Template");
+
+        format!("{}:{}", template_namespace, normalize_title(name))
+    }
+
+    /// Normalizes a title which may start with a namespace prefix, where
+    /// both the namespace and the rest of the title get normalized, so for
+    /// example "template:foo_bar" becomes "Template:Foo bar".
+    fn full_title(&self, title: &str) -> String {
+        match self.split_namespace(title) {
+            Some((namespace, rest)) => {
+                format!("{}:{}", namespace, normalize_title(rest))
+            },
+            None => normalize_title(title),
+        }
+    }
+
+    /// Returns the canonical name of the namespace the title starts with,
+    /// and the rest of the title, if it starts with a namespace other than
+    /// the main one.
+    fn split_namespace<'title>(&self, title: &'title str) -> Option<(&str, &'title str)> {
+        let namespaces = &self.site_info.namespaces;
+
+        let i = title.find(':')?;
+        let key = namespaces.key(&title[..i]).filter(|&key| key != 0)?;
+
+        Some((namespaces.name(key)?, &title[i + 1..]))
+    }
+
+    /// Returns the text of the page with the given title, following any
+    /// redirects.
+    fn template_body(&self, title: &str) -> Option<&str> {
+        let mut body = self.pages.get(title)?;
+
+        // Renamed templates leave a redirect behind, which still works.
+        for _ in 0..MAX_DEPTH {
+            let target = self.config.parse(body).nodes.iter().find_map(|node| match node {
+                Node::Redirect { target, .. } => {
+                    Some(self.full_title(target.trim_start_matches(':')))
+                },
+                _ => None,
+            });
+
+            match target {
+                Some(target) => body = self.pages.get(&target)?,
+                None => break,
+            }
+        }
+
+        Some(body)
+    }
+
+    /// Returns the override for the template with the given title, if it
+    /// is in the template namespace and there is one.
+    fn template_override(&self, title: &str) -> Option<&Override> {
+        let (namespace, name) = self.split_namespace(title)?;
+
+        if self.site_info.namespaces.key(namespace) != Some(TEMPLATE_NAMESPACE as i32) {
+            return None
+        }
+
+        self.overrides.get(name)
+    }
+
+    /// Returns whether the dump has a page with the given title, which
+    /// counts redirects as pages, like MediaWiki does. Templates that are
+    /// overridden count too, since using them works.
+    fn page_exists(&self, title: &str) -> bool {
+        let title = title.trim_start_matches(':');
+        if title.is_empty() {
+            return false
+        }
+
+        let title = self.full_title(title);
+
+        self.pages.contains_key(&title) || self.template_override(&title).is_some()
+    }
+}
+
+/// The arguments of a parser function call, which get expanded in the
+/// caller's frame on demand.
+struct LazyArguments<'e, 'a> {
+    expander: &'e Expander<'a>,
+    text: &'e str,
+    parameters: &'e [parse_wiki_text::Parameter<'e>],
+    frame: &'e Frame<'e>,
+}
+
+impl LazyArguments<'_, '_> {
+    fn expand(&self, source: &str) -> String {
+        self.expander.expand(source, self.frame).trim().to_owned()
+    }
+}
+
+impl parser_functions::Arguments for LazyArguments<'_, '_> {
+    fn count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    fn value(&self, index: usize) -> String {
+        match self.parameters.get(index) {
+            Some(parameter) => self.expand(parameter_source(self.text, parameter)),
+            None => String::new(),
+        }
+    }
+
+    fn case(&self, index: usize) -> Option<String> {
+        let name = self.parameters.get(index)?.name.as_ref()?;
+
+        Some(nodes_source(self.text, name).map(|name| self.expand(name)).unwrap_or_default())
+    }
+
+    fn case_value(&self, index: usize) -> String {
+        match self.parameters.get(index) {
+            Some(parameter) => self.expand(parameter_value_source(self.text, parameter)),
+            None => String::new(),
+        }
+    }
+}
+
+/// Returns the text of the whole template parameter, including the name if
+/// it has one.
+fn parameter_source<'text>(text: &'text str, parameter: &parse_wiki_text::Parameter) -> &'text str {
+    // The positions are trimmed, so an empty parameter can end before it
+    // starts.
+    text.get(parameter.start..parameter.end).unwrap_or("")
+}
+
+/// Returns the text of the template parameter after the name, if it has one.
+fn parameter_value_source<'text>(
+    text: &'text str,
+    parameter: &parse_wiki_text::Parameter
+) -> &'text str {
+    match parameter.value.first() {
+        Some(first) => text.get(first.start()..parameter.end).unwrap_or(""),
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE_INFO: &str = "<siteinfo><namespaces>\
+        <namespace key=\"0\" case=\"first-letter\" />\
+        <namespace key=\"10\" case=\"first-letter\">Template</namespace>\
+        </namespaces></siteinfo>";
+
+    /// Expands the text as the page "Test", with the given templates, by
+    /// name and body.
+    fn expand_page(templates: &[(String, String)], text: &str) -> (String, Vec<Diagnostic>) {
+        let config = Configuration::default();
+        let site_info = SiteInfo::parse(SITE_INFO).unwrap();
+        let interwiki = Interwiki::default();
+        let overrides = Overrides::default();
+        let markers = StripMarkers::default();
+
+        let pages: Vec<Page> = templates.iter()
+            .map(|(name, body)| Page {
+                format: None,
+                model: None,
+                namespace: TEMPLATE_NAMESPACE,
+                text: body.clone(),
+                title: format!("Template:{}", name),
+            })
+            .collect();
+
+        let expander = Expander::new(
+            &config,
+            &site_info,
+            &interwiki,
+            &overrides,
+            &markers,
+            pages.iter()
+        );
+
+        expander.expand_page("Test", text)
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn expands_templates_with_arguments() {
+        let templates = [("Greet".to_owned(), "Hello, {{{1}}}{{{2|!}}}".to_owned())];
+
+        let (expanded, diagnostics) = expand_page(&templates, "{{Greet|World}}");
+
+        assert_eq!(expanded, "Hello, World!");
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn detects_loops() {
+        let templates = [
+            ("A".to_owned(), "{{B}}".to_owned()),
+            ("B".to_owned(), "{{A}}".to_owned()),
+        ];
+
+        let (expanded, diagnostics) = expand_page(&templates, "{{A}}");
+
+        assert_eq!(
+            expanded,
+            "<strong class=\"error\">Template loop detected: [[:Template:A]]</strong>"
+        );
+        assert_eq!(messages(&diagnostics), ["Template loop detected"]);
+        assert_eq!(diagnostics[0].chain, ["Template:A", "Template:B", "Template:A"]);
+    }
+
+    #[test]
+    fn limits_the_depth() {
+        let templates: Vec<(String, String)> = (0..=MAX_DEPTH)
+            .map(|n| (format!("T{}", n), format!("{{{{T{}}}}}", n + 1)))
+            .collect();
+
+        let (_, diagnostics) = expand_page(&templates, "{{T0}}");
+
+        assert_eq!(messages(&diagnostics), ["Template recursion depth exceeded"]);
+        assert_eq!(diagnostics[0].chain.len(), MAX_DEPTH + 1);
+    }
+
+    #[test]
+    fn limits_the_expanded_size() {
+        let templates = [("Big".to_owned(), "x".repeat(MAX_EXPANDED_SIZE / 4 + 1))];
+
+        let (expanded, diagnostics) = expand_page(&templates, &"{{Big}}".repeat(6));
+
+        assert!(expanded.len() < MAX_EXPANDED_SIZE);
+        assert_eq!(messages(&diagnostics), [SIZE_LIMIT_EXCEEDED]);
+        assert_eq!(diagnostics[0].chain, ["Template:Big"]);
+    }
+
+    #[test]
+    fn limits_the_template_calls() {
+        // Each level calls the next twice, and the last is empty, so the
+        // size limit is never reached.
+        let templates: Vec<(String, String)> = (0..=20)
+            .map(|n| {
+                let body = if n < 20 {
+                    format!("{{{{A{0}}}}}{{{{A{0}}}}}", n + 1)
+                } else {
+                    String::new()
+                };
+                (format!("A{}", n), body)
+            })
+            .collect();
+
+        let (_, diagnostics) = expand_page(&templates, "{{A0}}");
+
+        assert_eq!(messages(&diagnostics), [CALL_LIMIT_EXCEEDED]);
+        assert_eq!(diagnostics[0].chain.first().map(String::as_str), Some("Template:A0"));
+    }
+}
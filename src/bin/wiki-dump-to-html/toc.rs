@@ -0,0 +1,174 @@
+use parse_wiki_text::{Node, Positioned};
+use std::io::Write;
+
+use super::{Escaped, Res, plain_text, section_anchor};
+
+/// The table of contents of a single page, along with the anchors to use
+/// for each heading.
+pub struct Toc {
+    entries: Vec<Entry>,
+    show: bool,
+    /// Whether `__TOC__` decides where the table goes, instead of it going
+    /// right before the first heading.
+    is_position_forced: bool,
+}
+
+struct Entry {
+    /// The byte position of the heading in the page text, which identifies
+    /// the heading while writing.
+    start: usize,
+    level: u8,
+    anchor: String,
+    text: String,
+}
+
+impl Toc {
+    /// Collects the headings from the top level nodes of the page, and
+    /// decides whether and where the table should be shown, the way
+    /// MediaWiki does: `__NOTOC__` always hides it, otherwise it is shown
+    /// if there are at least four headings, or if it was asked for with
+    /// `__TOC__` or `__FORCETOC__`.
+    pub fn new(page_text: &str, nodes: &[Node]) -> Self {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut is_hidden = false;
+        let mut is_forced = false;
+        let mut is_position_forced = false;
+
+        for node in nodes {
+            match node {
+                Node::Heading { level, nodes, start, .. } => {
+                    let text = plain_text(page_text, nodes);
+                    let base_anchor = section_anchor(&text);
+
+                    // A heading with no text, like `==<nowiki/>==`, has
+                    // nothing to link to, or to show in the table.
+                    if base_anchor.is_empty() {
+                        continue
+                    }
+
+                    // Repeated headings get numbered anchors, like MediaWiki.
+                    let mut anchor = base_anchor.clone();
+                    let mut counter = 2;
+                    while entries.iter().any(|e| e.anchor == anchor) {
+                        anchor = format!("{}_{}", base_anchor, counter);
+                        counter += 1;
+                    }
+
+                    entries.push(Entry {
+                        start: *start,
+                        level: *level,
+                        anchor,
+                        text,
+                    });
+                },
+                Node::MagicWord { .. } => {
+                    let word = page_text[node.start()..node.end()].trim_matches('_');
+                    if word.eq_ignore_ascii_case("NOTOC") {
+                        is_hidden = true;
+                    } else if word.eq_ignore_ascii_case("TOC") {
+                        is_forced = true;
+                        is_position_forced = true;
+                    } else if word.eq_ignore_ascii_case("FORCETOC") {
+                        is_forced = true;
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        let show = !is_hidden
+            && !entries.is_empty()
+            && (is_forced || entries.len() >= 4);
+
+        Toc {
+            entries,
+            show,
+            is_position_forced,
+        }
+    }
+
+    /// Returns the anchor for the heading starting at the given position.
+    pub fn anchor(&self, heading_start: usize) -> Option<&str> {
+        self.entries.iter()
+            .find(|e| e.start == heading_start)
+            .map(|e| e.anchor.as_str())
+    }
+
+    /// Returns whether the table should be written before the heading
+    /// starting at the given position.
+    pub fn goes_before_heading(&self, heading_start: usize) -> bool {
+        !self.is_position_forced
+        && self.entries.first().map(|e| e.start) == Some(heading_start)
+    }
+
+    /// Returns whether the table should be written in place of a `__TOC__`.
+    pub fn goes_at_magic_word(&self) -> bool {
+        self.is_position_forced
+    }
+
+    /// Writes the table, as numbered nested lists, if it should be shown.
+    pub fn write(&self, writer: &mut impl Write) -> Res<()> {
+        if !self.show {
+            return Ok(())
+        }
+
+        write!(writer, "<nav class=\"toc\"><h2>Contents</h2>")?;
+
+        // Each element is the level of a heading that contains the current
+        // one, along with how many headings have been seen at that depth.
+        let mut stack: Vec<(u8, usize)> = Vec::new();
+        let mut depth = 0;
+
+        for entry in self.entries.iter() {
+            let mut popped = None;
+            while stack.last().is_some_and(|&(level, _)| level > entry.level) {
+                popped = stack.pop();
+            }
+
+            match (stack.last_mut(), popped) {
+                (Some((level, count)), _) if *level == entry.level => {
+                    *count += 1;
+                },
+                // A heading that is less deep than the previous one, but
+                // still deeper than its parent, takes the previous one's
+                // place, like MediaWiki does it.
+                (_, Some((_, count))) => stack.push((entry.level, count + 1)),
+                _ => stack.push((entry.level, 1)),
+            }
+
+            let new_depth = stack.len();
+            if new_depth > depth {
+                for _ in depth..new_depth {
+                    write!(writer, "<ul>")?;
+                }
+            } else {
+                write!(writer, "</li>")?;
+                for _ in new_depth..depth {
+                    write!(writer, "</ul></li>")?;
+                }
+            }
+            depth = new_depth;
+
+            let number = stack.iter()
+                .map(|(_, count)| count.to_string())
+                .collect::<Vec<_>>()
+                .join(".");
+
+            write!(
+                writer,
+                "<li><a href=\"#{}\"><span class=\"toc-number\">{}</span> {}</a>",
+                Escaped(&entry.anchor),
+                number,
+                Escaped(&entry.text)
+            )?;
+        }
+
+        for _ in 0..depth {
+            write!(writer, "</li></ul>")?;
+        }
+
+        write!(writer, "</nav>")?;
+
+        Ok(())
+    }
+}
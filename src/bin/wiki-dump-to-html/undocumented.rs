@@ -0,0 +1,136 @@
+use parse_wiki_text::Node;
+use std::io::Write;
+
+use super::{
+    for_each_node,
+    plain_text,
+    template_name,
+    write_header,
+    Escaped,
+    Page,
+    Res,
+    Site,
+    Toc,
+    INDEX_FILE_NAME,
+};
+
+/// The name of the template that marks something as not being in the
+/// official manual.
+pub const UNDOCUMENTED_FEATURE: &str = "UndocumentedFeature";
+
+/// The title of the generated page that lists everything that is marked.
+pub const UNDOCUMENTED_FEATURES_TITLE: &str = "Undocumented features";
+
+/// Writes the warning that `{{UndocumentedFeature}}` stands for.
+pub fn write_callout(writer: &mut impl Write) -> Res<()> {
+    write!(
+        writer,
+        "<aside class=\"callout warning\" role=\"note\">\
+        <div class=\"callout-title\">Undocumented feature</div>\
+        <div>This article describes a feature that is not mentioned in the \
+        official PICO-8 documentation. The feature was known to work at the \
+        time the article was written, but it may be removed or changed in a \
+        future version.</div></aside>"
+    )?;
+
+    Ok(())
+}
+
+/// Where on a page an `{{UndocumentedFeature}}` is.
+pub struct Marker {
+    /// The text and anchor of the heading of the section the marker is in,
+    /// or `None` if it comes before the first heading, in which case it is
+    /// about the whole page.
+    pub section: Option<(String, String)>,
+}
+
+/// Returns the markers on the page, in order.
+pub fn find_markers(page_text: &str, nodes: &[Node]) -> Vec<Marker> {
+    let toc = Toc::new(page_text, nodes);
+
+    let mut markers = Vec::new();
+    let mut section = None;
+
+    for node in nodes {
+        if let Node::Heading { nodes, start, .. } = node {
+            section = toc.anchor(*start)
+                .map(|anchor| (plain_text(page_text, nodes), anchor.to_owned()));
+            continue
+        }
+
+        for_each_node(std::slice::from_ref(node), &mut |node| {
+            if let Node::Template { name, .. } = node {
+                if template_name(page_text, name) == UNDOCUMENTED_FEATURE {
+                    markers.push(Marker {
+                        section: section.clone(),
+                    });
+                }
+            }
+        });
+    }
+
+    markers
+}
+
+/// Writes the list of the pages and sections that are marked, so it's easy
+/// to check how much of what we rely on isn't in the manual.
+pub fn write_report(
+    writer: &mut impl Write,
+    site: &Site,
+    marked_pages: &[(&Page, Vec<Marker>)],
+) -> Res<()> {
+    write_header(writer, UNDOCUMENTED_FEATURES_TITLE, "")?;
+
+    write!(writer, "<nav><a href=\"{}\">Index</a></nav>", INDEX_FILE_NAME)?;
+    write!(writer, "<h1>{}</h1>", Escaped(UNDOCUMENTED_FEATURES_TITLE))?;
+
+    let count: usize = marked_pages.iter().map(|(_, markers)| markers.len()).sum();
+    write!(
+        writer,
+        "<p>These {} parts of {} pages describe features that are not in the \
+        official PICO-8 documentation, and may change in a future version.</p>",
+        count,
+        marked_pages.len()
+    )?;
+
+    write!(writer, "<ul class=\"undocumented\">")?;
+    for (page, markers) in marked_pages {
+        let file_name = site.file_names.get(&page.title).unwrap_or("");
+
+        write!(
+            writer,
+            "<li><a href=\"{}\">{}</a>",
+            Escaped(file_name),
+            Escaped(&page.title)
+        )?;
+
+        let sections: Vec<&(String, String)> = markers.iter()
+            .filter_map(|marker| marker.section.as_ref())
+            .collect();
+
+        if markers.iter().any(|marker| marker.section.is_none()) {
+            write!(writer, " (the whole page)")?;
+        }
+
+        if !sections.is_empty() {
+            write!(writer, "<ul>")?;
+            for (heading, anchor) in sections {
+                write!(
+                    writer,
+                    "<li><a href=\"{}#{}\">{}</a></li>",
+                    Escaped(file_name),
+                    Escaped(anchor),
+                    Escaped(heading)
+                )?;
+            }
+            write!(writer, "</ul>")?;
+        }
+
+        write!(writer, "</li>")?;
+    }
+    write!(writer, "</ul>")?;
+
+    write!(writer, "</body></html>")?;
+
+    Ok(())
+}